@@ -19,6 +19,9 @@ fn main() {
    // the renderer keeps track of the OpenGL state required to perform the
    // rendering like references to the shaders and buffers.
    let picasso_renderer = picasso::Renderer::new();
+   let green_to_blue = picasso::Gradient::new()
+      .add_color_stop(0.0, 0.1, 1.0, 0.1, 1.0)
+      .add_color_stop(1.0, 0.1, 0.1, 1.0, 1.0);
    let gradiated_rectangle = picasso::Canvas::new()
       .move_to(0.0, 0.0)
       .line_to(0.0, 100.0)
@@ -28,8 +31,7 @@ fn main() {
       .fill_linear_gradient(
          0.0, 0.0,
          100.0, 100.0,
-         0.1, 1.0, 0.1, 1.0,
-         0.1, 0.1, 1.0, 1.0,
+         &green_to_blue,
       );
 
 
@@ -0,0 +1,57 @@
+extern crate gl;
+extern crate picasso;
+
+extern crate glutin;
+use glutin::GlContext;
+
+mod window;
+
+
+fn main() {
+   // Create an OpenGL window
+   let (mut events_loop, window) = window::create_window();
+
+   let picasso_renderer = picasso::Renderer::new();
+
+   // Draw a busy background once into an offscreen target instead of every frame
+   let background = picasso::RenderTarget::new(200, 200);
+   let mut stripes = picasso::Canvas::new();
+   for i in 0..10 {
+      stripes = stripes
+         .rectangle(i as f32 * 20.0, 0.0, 10.0, 200.0)
+         .fill(0.2, 0.3, 0.8, 1.0);
+   }
+   let stripes = stripes
+      .circle(100.0, 100.0, 60.0)
+      .fill(0.9, 0.7, 0.1, 1.0);
+
+   background.clear(1.0, 1.0, 1.0, 1.0);
+   picasso_renderer.draw_canvas_to(&background, &stripes);
+
+   // The target's image can then be used to fill shapes, as many times as you like
+   let thumbnails = picasso::Canvas::new()
+      .rectangle(10.0, 10.0, 200.0, 200.0)
+      .fill_image(background.image(), 10.0, 10.0, 200.0, 200.0)
+      .circle(350.0, 110.0, 100.0)
+      .fill_image(background.image(), 250.0, 10.0, 200.0, 200.0)
+      .rectangle(500.0, 10.0, 100.0, 100.0)
+      .fill_image(background.image(), 500.0, 10.0, 100.0, 100.0);
+
+   let mut running = true;
+   while running {
+      // End the program if the user closes the window
+      events_loop.poll_events(|event| {
+         if let glutin::Event::WindowEvent { event: glutin::WindowEvent::Closed, .. } = event {
+            running = false;
+         }
+      });
+
+      unsafe {
+         gl::ClearColor(0.9, 0.2, 0.2, 1.0);
+         gl::Clear(gl::COLOR_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+      }
+
+      picasso_renderer.draw_canvas(800, 400, &thumbnails);
+      window.swap_buffers().unwrap();
+   }
+}
@@ -0,0 +1,50 @@
+extern crate gl;
+extern crate picasso;
+
+extern crate glutin;
+use glutin::GlContext;
+
+mod window;
+
+
+fn main() {
+   // Create an OpenGL window
+   let (mut events_loop, window) = window::create_window();
+
+   let picasso_renderer = picasso::Renderer::new();
+
+   // Strokes draw the outline of the path instead of filling it.
+   // The line width, joins and caps stay in effect until they are changed.
+   let zigzag = picasso::Canvas::new()
+      .line_width(12.0)
+      .line_join(picasso::LineJoin::Round)
+      .line_cap(picasso::LineCap::Round)
+      .move_to(50.0, 300.0)
+      .line_to(150.0, 100.0)
+      .line_to(250.0, 300.0)
+      .line_to(350.0, 100.0)
+      .stroke(0.2, 0.2, 0.8, 1.0)
+      .line_join(picasso::LineJoin::Miter)
+      .line_cap(picasso::LineCap::Butt)
+      .move_to(450.0, 300.0)
+      .bezier_curve_to(450.0, 50.0, 750.0, 350.0, 750.0, 100.0)
+      .stroke(0.2, 0.8, 0.2, 1.0);
+
+   let mut running = true;
+   while running {
+      // End the program if the user closes the window
+      events_loop.poll_events(|event| {
+         if let glutin::Event::WindowEvent { event: glutin::WindowEvent::Closed, .. } = event {
+            running = false;
+         }
+      });
+
+      unsafe {
+         gl::ClearColor(0.9, 0.9, 0.9, 1.0);
+         gl::Clear(gl::COLOR_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+      }
+
+      picasso_renderer.draw_canvas(800, 400, &zigzag);
+      window.swap_buffers().unwrap();
+   }
+}
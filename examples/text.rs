@@ -0,0 +1,60 @@
+extern crate gl;
+extern crate picasso;
+
+extern crate glutin;
+use glutin::GlContext;
+
+mod window;
+
+
+fn main() {
+   // Create an OpenGL window
+   let (mut events_loop, window) = window::create_window();
+
+   let picasso_renderer = picasso::Renderer::new();
+   let font = picasso::Font::new("examples/NotoSerif-Regular.ttf").unwrap();
+
+   // Text is added to the current shape, then painted by any fill
+   let gradient = picasso::Gradient::new()
+      .add_color_stop(0.0, 0.9, 0.3, 0.1, 1.0)
+      .add_color_stop(1.0, 0.2, 0.3, 0.9, 1.0);
+
+   // Paragraphs wrap and align text in a width
+   let paragraph = picasso::Paragraph::new(font.clone(), 18.0, 420.0)
+      .align(picasso::TextAlign::Justify)
+      .max_lines(4)
+      .layout("Longer text can be laid out as a paragraph, which breaks it into lines that fit a width, \
+         lines them up to the left, right, center or both sides, and cuts it off with an ellipsis \
+         when there's more of it than there are lines to show it in.");
+
+   let label = picasso::Canvas::new()
+      .fill_text(font.clone(), "Hello from Picasso", 20.0, 80.0, 48.0)
+      .fill(0.1, 0.1, 0.1, 1.0)
+      .fill_text(font.clone(), "Any fill works on text, gradients included", 20.0, 160.0, 32.0)
+      .fill_linear_gradient(20.0, 0.0, 640.0, 0.0, &gradient)
+      .translate(600.0, 300.0)
+      .rotate(-0.3)
+      .fill_text(font.clone(), "Transforms too", 0.0, 0.0, 28.0)
+      .fill(0.2, 0.5, 0.2, 1.0)
+      .set_transform(1.0, 0.0, 0.0, 1.0, 0.0, 0.0)
+      .fill_text_layout(&paragraph, 20.0, 200.0)
+      .fill(0.25, 0.25, 0.25, 1.0);
+
+   let mut running = true;
+   while running {
+      // End the program if the user closes the window
+      events_loop.poll_events(|event| {
+         if let glutin::Event::WindowEvent { event: glutin::WindowEvent::Closed, .. } = event {
+            running = false;
+         }
+      });
+
+      unsafe {
+         gl::ClearColor(0.95, 0.95, 0.9, 1.0);
+         gl::Clear(gl::COLOR_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+      }
+
+      picasso_renderer.draw_canvas(800, 400, &label);
+      window.swap_buffers().unwrap();
+   }
+}
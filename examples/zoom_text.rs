@@ -0,0 +1,53 @@
+extern crate gl;
+extern crate picasso;
+
+extern crate glutin;
+use glutin::GlContext;
+
+use std::time::Instant;
+
+mod window;
+
+
+fn main() {
+   // Create an OpenGL window
+   let (mut events_loop, window) = window::create_window();
+
+   // Every size of every glyph comes from one distance field, so zooming
+   // doesn't rasterize anything again
+   let mut picasso_renderer = picasso::Renderer::new();
+   picasso_renderer.set_text_rendering(picasso::TextRendering::DistanceField);
+
+   let font = picasso::Font::new("examples/NotoSerif-Regular.ttf").unwrap();
+   let start = Instant::now();
+
+   let mut running = true;
+   while running {
+      // End the program if the user closes the window
+      events_loop.poll_events(|event| {
+         if let glutin::Event::WindowEvent { event: glutin::WindowEvent::Closed, .. } = event {
+            running = false;
+         }
+      });
+
+      // Zoom in and out around the middle of the window, turning slowly
+      let elapsed = start.elapsed();
+      let seconds = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 / 1_000_000_000.0;
+      let zoom = 0.5 + 7.5 * (0.5 - 0.5 * (seconds * 0.5).cos());
+
+      let label = picasso::Canvas::new()
+         .translate(400.0, 200.0)
+         .rotate(seconds * 0.1)
+         .scale(zoom, zoom)
+         .fill_text(font.clone(), "Zoom", -50.0, 10.0, 36.0)
+         .fill(0.1, 0.1, 0.1, 1.0);
+
+      unsafe {
+         gl::ClearColor(0.95, 0.95, 0.9, 1.0);
+         gl::Clear(gl::COLOR_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+      }
+
+      picasso_renderer.draw_canvas(800, 400, &label);
+      window.swap_buffers().unwrap();
+   }
+}
@@ -1,8 +1,26 @@
 use std::mem;
 use std::rc::Rc;
+use std::f32::consts;
+use super::geometry;
 use super::geometry::Matrix;
 use super::geometry::Point;
 use super::Image;
+use super::Gradient;
+use super::Pattern;
+use super::{Font, FontFamily};
+use super::font::TextRun;
+use super::TextLayout;
+use super::stroke;
+use super::SoftwareRenderer;
+use std::path::Path;
+use imagefmt;
+use rusttype;
+use rusttype::Segment;
+use super::stroke::{StrokeStyle, LineJoin, LineCap};
+
+
+// How far, in pixels, a flattened curve may stray from the true curve
+const FLATTENING_TOLERANCE: f32 = 0.25;
 
 /// Used to create shapes by calling `line_to` and `move_to`.
 /// Pass this to a Window to render
@@ -10,8 +28,19 @@ use super::Image;
 pub struct Canvas {
   points: Vec<Point>,
   figures: Vec<Figure>,
-  path_in_progress: Vec<(usize, usize)>,
+  path_in_progress: Vec<Subpath>,
+  text_in_progress: Vec<TextRun>,
+  state: State,
+  saved_states: Vec<State>,
+}
+
+
+// Everything that `save` and `restore` remember
+#[derive(Debug, Clone)]
+struct State {
   transform: Matrix,
+  stroke_style: StrokeStyle,
+  fill_rule: FillRule,
 }
 
 
@@ -22,31 +51,143 @@ impl Canvas {
          points: vec![],
          figures: vec![],
          path_in_progress: vec![],
-         transform: Matrix::identity(),
+         text_in_progress: vec![],
+         state: State {
+            transform: Matrix::identity(),
+            stroke_style: StrokeStyle::new(),
+            fill_rule: FillRule::NonZero,
+         },
+         saved_states: vec![],
+      }
+   }
+
+
+   /// Remember the current transform, stroke style and fill rule so they can be brought back with `restore`.
+   /// Calls to `save` and `restore` nest.
+   pub fn save(mut self) -> Self {
+      self.saved_states.push(self.state.clone());
+      self
+   }
+
+
+   /// Return to the transform, stroke style and fill rule of the matching `save`. Does nothing if nothing was saved.
+   pub fn restore(mut self) -> Self {
+      if let Some(state) = self.saved_states.pop() {
+         self.state = state;
       }
+
+      self
+   }
+
+
+   /// The transform applied to everything drawn from now on.
+   /// Its inverse maps canvas coordinates, like a mouse position, back into the current coordinate system.
+   pub fn current_transform(&self) -> Matrix {
+      self.state.transform
    }
 
 
+   /// Rotate everything drawn from now on by `angle` radians around the current origin
    pub fn rotate(mut self, angle: f32) -> Self {
-      self.transform = self.transform * Matrix::from_rotation(angle);
+      self.state.transform = self.state.transform * Matrix::from_rotation(angle);
       self
    }
 
 
+   /// Move the origin of everything drawn from now on
    pub fn translate(mut self, x: f32, y: f32) -> Self {
-      self.transform = self.transform * Matrix::from_translation(x, y);
+      self.state.transform = self.state.transform * Matrix::from_translation(x, y);
+      self
+   }
+
+
+   /// Stretch everything drawn from now on, relative to the current origin
+   pub fn scale(mut self, x: f32, y: f32) -> Self {
+      self.state.transform = self.state.transform * Matrix::from_scale(x, y);
+      self
+   }
+
+
+   /// Slant vertical lines drawn from now on by `angle` radians
+   pub fn skew_x(mut self, angle: f32) -> Self {
+      self.state.transform = self.state.transform * Matrix::from_skew_x(angle);
+      self
+   }
+
+
+   /// Slant horizontal lines drawn from now on by `angle` radians
+   pub fn skew_y(mut self, angle: f32) -> Self {
+      self.state.transform = self.state.transform * Matrix::from_skew_y(angle);
+      self
+   }
+
+
+   /// Multiply the current transform by an arbitrary affine matrix.
+   /// The arguments are in the same order as the HTML canvas: see `Matrix::new`.
+   pub fn transform(mut self, a: f32, b: f32, c: f32, d: f32, e: f32, f: f32) -> Self {
+      self.state.transform = self.state.transform * Matrix::new(a, b, c, d, e, f);
+      self
+   }
+
+
+   /// Replace the current transform with an arbitrary affine matrix
+   pub fn set_transform(mut self, a: f32, b: f32, c: f32, d: f32, e: f32, f: f32) -> Self {
+      self.state.transform = Matrix::new(a, b, c, d, e, f);
       self
    }
 
 
    /// Draw a line to the provided points
    pub fn line_to(mut self, x: f32, y: f32) -> Self {
-      self.points.push(Point::new(x, y));
+      let point = self.state.transform * Point::new(x, y);
+      self.add_point(point);
+      self
+   }
+
 
+   /// Draw a curve from the current point to (x, y) that bends towards the control point
+   pub fn quadratic_curve_to(mut self, control_x: f32, control_y: f32, x: f32, y: f32) -> Self {
       if self.path_in_progress.len() == 0 {
-         self.path_in_progress.push((self.points.len() - 1, 1));
-      } else {
-         self.path_in_progress.last_mut().unwrap().1 += 1;
+         self = self.move_to(control_x, control_y);
+      }
+
+      let start = self.current_device_point().unwrap();
+      let control = self.state.transform * Point::new(control_x, control_y);
+      let end = self.state.transform * Point::new(x, y);
+
+      // Curves are flattened after transforming so that zooming in adds more line segments
+      let mut points = vec![];
+      geometry::flatten_quadratic(start, control, end, FLATTENING_TOLERANCE, &mut points);
+
+      for point in points {
+         self.add_point(point);
+      }
+
+      self
+   }
+
+
+   /// Draw a cubic Bézier curve from the current point to (x, y), shaped by two control points
+   pub fn bezier_curve_to(
+      mut self,
+      control_1_x: f32, control_1_y: f32,
+      control_2_x: f32, control_2_y: f32,
+      x: f32, y: f32,
+   ) -> Self {
+      if self.path_in_progress.len() == 0 {
+         self = self.move_to(control_1_x, control_1_y);
+      }
+
+      let start = self.current_device_point().unwrap();
+      let control_1 = self.state.transform * Point::new(control_1_x, control_1_y);
+      let control_2 = self.state.transform * Point::new(control_2_x, control_2_y);
+      let end = self.state.transform * Point::new(x, y);
+
+      let mut points = vec![];
+      geometry::flatten_cubic(start, control_1, control_2, end, FLATTENING_TOLERANCE, &mut points);
+
+      for point in points {
+         self.add_point(point);
       }
 
       self
@@ -54,9 +195,38 @@ impl Canvas {
 
 
    /// Move the virtual "pen" to new coordinates without connecting them with a line
+   /// This starts a new subpath.
    pub fn move_to(mut self, x: f32, y: f32) -> Self {
-      self.points.push(Point::new(x, y));
-      self.path_in_progress.push((self.points.len() - 1, 1));
+      let point = self.state.transform * Point::new(x, y);
+
+      // Two moves in a row only need to remember the second one
+      if let Some(&Subpath {index, length: 1, ..}) = self.path_in_progress.last() {
+         if index == self.points.len() - 1 {
+            self.points[index] = point;
+            return self;
+         }
+      }
+
+      self.points.push(point);
+      self.path_in_progress.push(Subpath {index: self.points.len() - 1, length: 1, closed: false, convex: false});
+      self
+   }
+
+
+   /// Connect the end of the current subpath back to its start.
+   /// Strokes join closed subpaths at the start point instead of giving them caps.
+   /// Drawing further continues from the start point in a new subpath.
+   pub fn close_path(mut self) -> Self {
+      let points = &self.points;
+
+      // A closed subpath is finished, so this is the time to find out whether it's convex
+      if let Some(subpath) = self.path_in_progress.last_mut() {
+         if !subpath.closed {
+            subpath.closed = true;
+            subpath.convex = geometry::is_convex(&points[subpath.index..subpath.index + subpath.length]);
+         }
+      }
+
       self
    }
 
@@ -68,61 +238,452 @@ impl Canvas {
          .line_to(x + width, y)
          .line_to(x + width, y + height)
          .line_to(x, y + height)
+         .close_path()
+   }
+
+
+   /// Draw a rectangle with rounded corners. Each corner gets its own radius,
+   /// and the radii shrink proportionally if they don't fit in the rectangle.
+   pub fn rounded_rectangle(
+      self,
+      x: f32, y: f32,
+      width: f32, height: f32,
+      top_left: f32, top_right: f32, bottom_right: f32, bottom_left: f32,
+   ) -> Self {
+      let fit = [
+         1.0,
+         width / (top_left + top_right),
+         width / (bottom_left + bottom_right),
+         height / (top_left + bottom_left),
+         height / (top_right + bottom_right),
+      ].iter().fold(1.0f32, |fit, &ratio| if ratio.is_finite() { fit.min(ratio.abs()) } else { fit });
+
+      let (top_left, top_right) = (top_left.max(0.0) * fit, top_right.max(0.0) * fit);
+      let (bottom_right, bottom_left) = (bottom_right.max(0.0) * fit, bottom_left.max(0.0) * fit);
+      let quarter = consts::PI / 2.0;
+
+      self
+         .move_to(x + top_left, y)
+         .arc(x + width - top_right, y + top_right, top_right, -quarter, 0.0, false)
+         .arc(x + width - bottom_right, y + height - bottom_right, bottom_right, 0.0, quarter, false)
+         .arc(x + bottom_left, y + height - bottom_left, bottom_left, quarter, 2.0 * quarter, false)
+         .arc(x + top_left, y + top_left, top_left, 2.0 * quarter, 3.0 * quarter, false)
+         .close_path()
+   }
+
+
+   /// Draw a circle as its own shape
+   pub fn circle(self, center_x: f32, center_y: f32, radius: f32) -> Self {
+      self
+         .move_to(center_x + radius, center_y)
+         .arc(center_x, center_y, radius, 0.0, 2.0 * consts::PI, false)
+         .close_path()
+   }
+
+
+   /// Draw part of a circle, connected to the current path by a straight line.
+   /// Angles are in radians, measured clockwise from the positive x axis.
+   pub fn arc(self, center_x: f32, center_y: f32, radius: f32, start_angle: f32, end_angle: f32, anticlockwise: bool) -> Self {
+      self.ellipse(center_x, center_y, radius, radius, 0.0, start_angle, end_angle, anticlockwise)
+   }
+
+
+   /// Draw part of an ellipse, connected to the current path by a straight line.
+   /// The ellipse is rotated by `rotation` radians and the angles are measured before that rotation.
+   pub fn ellipse(
+      mut self,
+      center_x: f32, center_y: f32,
+      radius_x: f32, radius_y: f32,
+      rotation: f32,
+      start_angle: f32, end_angle: f32,
+      anticlockwise: bool,
+   ) -> Self {
+      let full_turn = 2.0 * consts::PI;
+      let sweep = if anticlockwise {
+         if start_angle - end_angle >= full_turn { -full_turn } else { -(start_angle - end_angle).rem_euclid(full_turn) }
+      } else {
+         if end_angle - start_angle >= full_turn { full_turn } else { (end_angle - start_angle).rem_euclid(full_turn) }
+      };
+
+      // Pick the number of segments so that the flattened arc strays no more than the tolerance once transformed
+      let radius = radius_x.abs().max(radius_y.abs()) * self.state.transform.max_scale();
+      let step = if radius > FLATTENING_TOLERANCE {
+         2.0 * (1.0 - FLATTENING_TOLERANCE / radius).acos()
+      } else {
+         consts::PI / 2.0
+      };
+      let segments = ((sweep.abs() / step).ceil() as usize).max(1).min(1000);
+
+      let rotation = Matrix::from_translation(center_x, center_y) * Matrix::from_rotation(rotation);
+
+      for i in 0..segments + 1 {
+         let angle = start_angle + sweep * i as f32 / segments as f32;
+         let point = rotation * Point::new(radius_x * angle.cos(), radius_y * angle.sin());
+
+         self = if i == 0 && self.path_in_progress.len() == 0 {
+            self.move_to(point.x, point.y)
+         } else {
+            self.line_to(point.x, point.y)
+         };
+      }
+
+      self
+   }
+
+
+   /// Draw a line from the current point towards (x1, y1) that turns towards (x2, y2)
+   /// with a rounded corner of the given radius, like the HTML canvas's `arcTo`
+   pub fn arc_to(self, x1: f32, y1: f32, x2: f32, y2: f32, radius: f32) -> Self {
+      let start = match self.current_point() {
+         Some(point) => point,
+         None => return self.move_to(x1, y1),
+      };
+
+      let corner = Point::new(x1, y1);
+      let (to_start_x, to_start_y) = (start.x - corner.x, start.y - corner.y);
+      let (to_end_x, to_end_y) = (x2 - corner.x, y2 - corner.y);
+      let to_start_length = (to_start_x * to_start_x + to_start_y * to_start_y).sqrt();
+      let to_end_length = (to_end_x * to_end_x + to_end_y * to_end_y).sqrt();
+      let cross = to_start_x * to_end_y - to_start_y * to_end_x;
+
+      // Without a proper corner there's nothing to round
+      if radius <= 0.0 || to_start_length == 0.0 || to_end_length == 0.0 || cross == 0.0 {
+         return self.line_to(x1, y1);
+      }
+
+      let (to_start_x, to_start_y) = (to_start_x / to_start_length, to_start_y / to_start_length);
+      let (to_end_x, to_end_y) = (to_end_x / to_end_length, to_end_y / to_end_length);
+
+      // The circle touches both lines, so its center is on the line halfway between them
+      let corner_angle = (to_start_x * to_end_x + to_start_y * to_end_y).max(-1.0).min(1.0).acos();
+      let tangent_distance = radius / (corner_angle / 2.0).tan();
+      let center_distance = radius / (corner_angle / 2.0).sin();
+      let (bisector_x, bisector_y) = (to_start_x + to_end_x, to_start_y + to_end_y);
+      let bisector_length = (bisector_x * bisector_x + bisector_y * bisector_y).sqrt();
+
+      let center = Point::new(
+         corner.x + bisector_x / bisector_length * center_distance,
+         corner.y + bisector_y / bisector_length * center_distance,
+      );
+      let tangent_start = Point::new(corner.x + to_start_x * tangent_distance, corner.y + to_start_y * tangent_distance);
+      let tangent_end = Point::new(corner.x + to_end_x * tangent_distance, corner.y + to_end_y * tangent_distance);
+
+      let start_angle = (tangent_start.y - center.y).atan2(tangent_start.x - center.x);
+      let end_angle = (tangent_end.y - center.y).atan2(tangent_end.x - center.x);
+
+      self.arc(center.x, center.y, radius, start_angle, end_angle, cross > 0.0)
+   }
+
+
+   /// Add a line of text to the current shape, `size` units tall with its baseline starting at (x, y).
+   /// Like any other shape, the text is painted by the next fill: `fill`, a gradient, an image or a pattern.
+   /// Nothing is drawn until then. Strokes leave the text waiting for the next fill. To outline text, use `text_path`.
+   pub fn fill_text(mut self, font: Rc<Font>, text: &str, x: f32, y: f32, size: f32) -> Self {
+      let transform = self.state.transform;
+      let origin = transform * Point::new(x, y);
+      let scale = transform.max_scale();
+
+      if !(size > 0.0) || !(scale > 0.0) || !origin.x.is_finite() || !origin.y.is_finite() {
+         return self;
+      }
+
+      // Glyphs are rasterized at the size they'll appear on the canvas, in a coordinate system
+      // of their own that's only rotated, skewed or stretched if the canvas is. Without any
+      // of that, glyph pixels land exactly on canvas pixels.
+      let corner = Point::new(origin.x.floor(), origin.y.floor());
+      let translation = transform * Point::new(0.0, 0.0);
+      let linear = Matrix::from_translation(-translation.x, -translation.y) * transform;
+      let glyph_to_canvas = linear * Matrix::from_scale(1.0 / scale, 1.0 / scale);
+      let canvas_to_glyph = match glyph_to_canvas.inverse() {
+         Some(inverse) => inverse,
+         None => return self,
+      };
+
+      let start = canvas_to_glyph * Point::new(origin.x - corner.x, origin.y - corner.y);
+
+      self.text_in_progress.push(TextRun {
+         glyphs: font.layout(text, size * scale, start),
+         font: font,
+         transform: Matrix::from_translation(corner.x, corner.y) * glyph_to_canvas,
+      });
+
+      self
+   }
+
+
+   /// Add a paragraph laid out by `Paragraph::layout` to the current shape, with its top left corner at (x, y).
+   /// Like `fill_text`, it's painted by the next fill.
+   pub fn fill_text_layout(mut self, layout: &TextLayout, x: f32, y: f32) -> Self {
+      for line in &layout.lines {
+         for &(span_x, ref text) in &line.spans {
+            self = self.fill_text(layout.font.clone(), text, x + span_x, y + line.baseline, layout.size);
+         }
+      }
+
+      self
+   }
+
+
+   /// Add the outlines of a line of text to the current path, `size` units tall with its baseline starting at (x, y).
+   /// Unlike `fill_text`, the glyphs become ordinary subpaths, so they stay sharp however they're scaled or rotated
+   /// and can be stroked as well as filled. Fill them with `FillRule::NonZero`, the default.
+   pub fn text_path(mut self, font: &Font, text: &str, x: f32, y: f32, size: f32) -> Self {
+      if !(size > 0.0) {
+         return self;
+      }
+
+      // Glyph outlines point up, the canvas down
+      let place = |point: rusttype::Point<f32>| (x + point.x, y - point.y);
+
+      for contour in font.outline(text, size) {
+         let mut pen = None;
+
+         for segment in &contour.segments {
+            let (start, end) = match *segment {
+               Segment::Line(ref line) => (line.p[0], line.p[1]),
+               Segment::Curve(ref curve) => (curve.p[0], curve.p[2]),
+            };
+
+            // rusttype sometimes puts two outlines in one contour, so a jump starts a new subpath
+            if pen != Some(start) {
+               if pen.is_some() {
+                  self = self.close_path();
+               }
+
+               let (start_x, start_y) = place(start);
+               self = self.move_to(start_x, start_y);
+            }
+
+            pen = Some(end);
+
+            self = match *segment {
+               Segment::Line(ref line) => {
+                  let (end_x, end_y) = place(line.p[1]);
+                  self.line_to(end_x, end_y)
+               }
+               Segment::Curve(ref curve) => {
+                  let (control_x, control_y) = place(curve.p[1]);
+                  let (end_x, end_y) = place(curve.p[2]);
+                  self.quadratic_curve_to(control_x, control_y, end_x, end_y)
+               }
+            };
+         }
+
+         self = self.close_path();
+      }
+
+      self
+   }
+
+
+   /// Like `fill_text`, but each character is drawn with the first font in the family that has it
+   pub fn fill_text_family(mut self, family: &FontFamily, text: &str, x: f32, y: f32, size: f32) -> Self {
+      let mut x = x;
+
+      for (font, run) in family.runs(text) {
+         let width = font.measure(&run, size).width;
+         self = self.fill_text(font, &run, x, y, size);
+         x += width;
+      }
+
+      self
+   }
+
+
+   /// Like `text_path`, but each character's outline comes from the first font in the family that has it
+   pub fn text_path_family(mut self, family: &FontFamily, text: &str, x: f32, y: f32, size: f32) -> Self {
+      let mut x = x;
+
+      for (font, run) in family.runs(text) {
+         self = self.text_path(&font, &run, x, y, size);
+         x += font.measure(&run, size).width;
+      }
+
+      self
+   }
+
+
+   /// Choose how fills decide which parts of overlapping subpaths are inside the shape. Defaults to `FillRule::NonZero`.
+   pub fn fill_rule(mut self, rule: FillRule) -> Self {
+      self.state.fill_rule = rule;
+      self
    }
 
 
    /// Complete the current shape by giving it a fill
    pub fn fill(mut self, red: f32, green: f32, blue: f32, alpha: f32) -> Self {
-      self.figures.push(Figure {fill: Fill::SolidColor((red, green, blue, alpha)), paths: self.path_in_progress});
-      self.path_in_progress = vec![];
+      let fill_rule = self.state.fill_rule;
+      self.push_figure(Fill::SolidColor((red, green, blue, alpha)), fill_rule);
+      self
+   }
+
+
+   /// Set the width of strokes, in the coordinate system they are stroked in. Defaults to 1.
+   pub fn line_width(mut self, width: f32) -> Self {
+      self.state.stroke_style.width = width;
+      self
+   }
+
+
+   /// Set the shape of the corners of strokes. Defaults to `LineJoin::Miter`.
+   pub fn line_join(mut self, join: LineJoin) -> Self {
+      self.state.stroke_style.join = join;
+      self
+   }
+
+
+   /// Set the shape of the ends of open strokes. Defaults to `LineCap::Butt`.
+   pub fn line_cap(mut self, cap: LineCap) -> Self {
+      self.state.stroke_style.cap = cap;
+      self
+   }
+
+
+   /// Set how far, as a multiple of the line width, a miter join may reach before it is beveled instead. Defaults to 10.
+   pub fn miter_limit(mut self, limit: f32) -> Self {
+      self.state.stroke_style.miter_limit = limit;
+      self
+   }
+
+
+   /// Make strokes dashed. The segments alternate between dash and gap lengths, and are repeated
+   /// twice over if there's an odd number of them. An empty slice, or one of all zeros, goes back to solid lines.
+   /// Does nothing if any length is negative or not a number.
+   pub fn set_line_dash(mut self, segments: &[f32]) -> Self {
+      if segments.iter().any(|length| !(*length >= 0.0) || !length.is_finite()) {
+         return self;
+      }
+
+      let mut dash = segments.to_vec();
+
+      if dash.len() % 2 == 1 {
+         dash.extend_from_slice(segments);
+      }
+
+      self.state.stroke_style.dash = dash;
+      self
+   }
+
+
+   /// Shift the dash pattern along strokes by `offset`. Animating it makes the dashes crawl along the line.
+   pub fn line_dash_offset(mut self, offset: f32) -> Self {
+      self.state.stroke_style.dash_offset = offset;
+      self
+   }
+
+
+   /// Complete the current shape by drawing its outline with the current stroke style.
+   /// Text added with `fill_text` has no outline to stroke, so it waits for the next fill.
+   pub fn stroke(mut self, red: f32, green: f32, blue: f32, alpha: f32) -> Self {
+      // The pieces of a stroke overlap, and each overlap would be a hole with the even-odd rule
+      self.path_in_progress = self.stroke_outline();
+      let text = mem::replace(&mut self.text_in_progress, vec![]);
+      self.push_figure(Fill::SolidColor((red, green, blue, alpha)), FillRule::NonZero);
+      self.text_in_progress = text;
       self
    }
 
 
    pub fn fill_image(mut self, image: Rc<Image>, x: f32, y: f32, width: f32, height: f32) -> Self {
-      self.figures.push(Figure {
-         fill: Fill::Image(image, Point::new(x, y), width, height),
-         paths: self.path_in_progress,
-      });
+      let fill_rule = self.state.fill_rule;
+      self.push_figure(Fill::Image(image, Point::new(x, y), width, height), fill_rule);
+      self
+   }
 
-      self.path_in_progress = vec![];
 
+   /// Fill with an image tiled according to the pattern
+   pub fn fill_pattern(mut self, pattern: &Pattern) -> Self {
+      let fill_rule = self.state.fill_rule;
+      self.push_figure(Fill::Pattern(pattern.clone()), fill_rule);
       self
    }
 
 
+   /// Fill with colors that change along the line from begin to end.
+   /// Offset 0.0 of the gradient is at the begin point and 1.0 at the end point.
    pub fn fill_linear_gradient(
       mut self,
       begin_x: f32, begin_y: f32,
       end_x: f32,   end_y: f32,
-      begin_red: f32, begin_green: f32, begin_blue: f32, begin_alpha: f32,
-      end_red: f32,   end_green: f32,   end_blue: f32,   end_alpha: f32,
+      gradient: &Gradient,
    ) -> Self {
       let begin = Point::new(begin_x, begin_y);
       let end = Point::new(end_x, end_y);
-      let begin_color = (begin_red, begin_green, begin_blue, begin_alpha);
-      let end_color = (end_red, end_green, end_blue, end_alpha);
 
-      self.figures.push(Figure {
-         fill: Fill::LinearGradient(begin, end, begin_color, end_color),
-         paths: self.path_in_progress
-      });
+      let fill_rule = self.state.fill_rule;
+      self.push_figure(Fill::LinearGradient(begin, end, gradient.clone()), fill_rule);
+      self
+   }
+
+
+   /// Fill with colors that change between two circles, like the HTML canvas's `createRadialGradient`.
+   /// Offset 0.0 of the gradient is on the first circle and 1.0 on the second, with a
+   /// blend on the circles in between. Areas no circle passes through are left transparent.
+   pub fn fill_radial_gradient(
+      mut self,
+      begin_x: f32, begin_y: f32, begin_radius: f32,
+      end_x: f32,   end_y: f32,   end_radius: f32,
+      gradient: &Gradient,
+   ) -> Self {
+      let begin = Point::new(begin_x, begin_y);
+      let end = Point::new(end_x, end_y);
 
-      self.path_in_progress = vec![];
+      let fill_rule = self.state.fill_rule;
+      self.push_figure(Fill::RadialGradient(begin, begin_radius, end, end_radius, gradient.clone()), fill_rule);
+      self
+   }
+
+
+   /// Fill with colors that sweep clockwise around a center point. Offset 0.0 of the
+   /// gradient is at `start_angle` radians and 1.0 is all the way back around to it.
+   pub fn fill_conic_gradient(
+      mut self,
+      center_x: f32, center_y: f32,
+      start_angle: f32,
+      gradient: &Gradient,
+   ) -> Self {
+      let center = Point::new(center_x, center_y);
 
+      let fill_rule = self.state.fill_rule;
+      self.push_figure(Fill::ConicGradient(center, start_angle, gradient.clone()), fill_rule);
       self
    }
 
 
-   /// Copy another canvas to this one
+   /// Draw the canvas onto a transparent background without OpenGL, returning
+   /// RGBA pixels, 8 bits per channel, starting with the top row
+   pub fn render_to_buffer(&self, width: u32, height: u32) -> Vec<u8> {
+      let mut renderer = SoftwareRenderer::new(width, height);
+      renderer.draw_canvas(self);
+      renderer.into_pixels()
+   }
+
+
+   /// Draw the canvas onto a transparent background without OpenGL and save it to
+   /// a PNG, TGA or BMP file, picked by the file's extension. JPEG isn't supported:
+   /// a .jpg or .jpeg path gives an `Unsupported` error.
+   pub fn render_to_file<P: AsRef<Path>>(&self, path: P, width: u32, height: u32) -> Result<(), imagefmt::Error> {
+      let mut renderer = SoftwareRenderer::new(width, height);
+      renderer.draw_canvas(self);
+      renderer.save(path)
+   }
+
+
+   /// Copy another canvas to this one.
+   /// The other canvas is placed in the current coordinate system, so
+   /// translating or rotating before attaching moves the whole attached canvas.
    pub fn attach(mut self, other: &Canvas) -> Self {
       let offset = self.points.len();
+      let transform = self.state.transform;
 
-      self.points.extend(other.points.iter().map(|point| *point));
+      self.points.extend(other.points.iter().map(|point| transform * *point));
       self.figures.extend(other.figures.iter().map(|figure| {
          Figure {
             fill: figure.fill.clone(),
-            paths: figure.paths.iter().map(|&(index, length)| (index + offset, length)).collect()
+            paths: figure.paths.iter().map(|&path| Subpath {index: path.index + offset, ..path}).collect(),
+            text: figure.text.iter().map(|run| TextRun {transform: transform * run.transform, ..run.clone()}).collect(),
+            transform: transform * figure.transform,
+            fill_rule: figure.fill_rule,
          }
       }));
 
@@ -133,16 +694,107 @@ impl Canvas {
 
 
 impl Canvas {
+   // Where the pen is, in canvas coordinates. After closing a subpath that's back at its start.
+   fn current_device_point(&self) -> Option<Point> {
+      self.path_in_progress.last().map(|path| {
+         if path.closed {
+            self.points[path.index]
+         } else {
+            self.points[path.index + path.length - 1]
+         }
+      })
+   }
+
+
+   // Where the pen is, in the current coordinate system
+   fn current_point(&self) -> Option<Point> {
+      let inverse = self.state.transform.inverse().unwrap_or(Matrix::identity());
+      self.current_device_point().map(|point| inverse * point)
+   }
+
+
+   // Finish the current path and text as a figure with the given fill.
+   // Subpaths with fewer than three points have no area, so they're left out.
+   // Closed subpaths already know whether they're convex. Open ones are closed by the fill.
+   fn push_figure(&mut self, fill: Fill, fill_rule: FillRule) {
+      let paths = mem::replace(&mut self.path_in_progress, vec![]);
+      let text = mem::replace(&mut self.text_in_progress, vec![]);
+
+      let points = &self.points;
+
+      self.figures.push(Figure {
+         fill: fill,
+         paths: paths.into_iter().filter(|path| path.length >= 3).map(|path| Subpath {
+            convex: if path.closed { path.convex } else { geometry::is_convex(&points[path.index..path.index + path.length]) },
+            ..path
+         }).collect(),
+         text: text,
+         transform: self.state.transform,
+         fill_rule: fill_rule,
+      });
+   }
+
+
+   // Replace the current path with the polygons making up its stroke.
+   // The stroke is built in the current coordinate system so the line width
+   // and round joins are transformed along with everything else.
+   fn stroke_outline(&mut self) -> Vec<Subpath> {
+      let paths = mem::replace(&mut self.path_in_progress, vec![]);
+      let transform = self.state.transform;
+      let inverse = match transform.inverse() {
+         Some(inverse) => inverse,
+         None => return vec![],
+      };
+
+      let tolerance = FLATTENING_TOLERANCE / transform.max_scale();
+      let mut polygons = vec![];
+
+      for path in paths {
+         let points: Vec<Point> = self.points[path.index..path.index + path.length].iter().map(|&point| inverse * point).collect();
+         stroke::stroke_polyline(&points, path.closed, &self.state.stroke_style, tolerance, &mut polygons);
+      }
+
+      polygons.into_iter().map(|polygon| {
+         let index = self.points.len();
+         self.points.extend(polygon.iter().map(|&point| transform * point));
+         Subpath {index: index, length: polygon.len(), closed: true, convex: true}
+      }).collect()
+   }
+
+
+   // Append a point that has already been transformed into canvas coordinates to the current path
+   fn add_point(&mut self, point: Point) {
+      // Drawing after closing a subpath starts a new one at the closed subpath's start
+      if let Some(&Subpath {index, closed: true, ..}) = self.path_in_progress.last() {
+         let start = self.points[index];
+         self.points.push(start);
+         self.path_in_progress.push(Subpath {index: self.points.len() - 1, length: 1, closed: false, convex: false});
+      }
+
+      self.points.push(point);
+
+      if self.path_in_progress.len() == 0 {
+         self.path_in_progress.push(Subpath {index: self.points.len() - 1, length: 1, closed: false, convex: false});
+      } else {
+         self.path_in_progress.last_mut().unwrap().length += 1;
+      }
+   }
+
+
    pub(crate) fn figures_iter<'a>(&'a self) -> Box<::std::iter::Iterator<Item = &Figure> + 'a> {
       Box::new(self.figures.iter())
    }
 
 
-   // Return an unsafe pointer to the point data for this figure
-   // (buffer_length, buffer_pointer)
-   pub(crate) unsafe fn get_points_buffer(&self) -> (*const Point, usize) {
+   pub(crate) fn points(&self) -> &[Point] {
+      &self.points
+   }
+
+
+   // A pointer to the points and their size in bytes. The pointer is dangling if there are no points.
+   pub(crate) fn get_points_buffer(&self) -> (*const Point, usize) {
       (
-         mem::transmute(&self.points[0]),
+         self.points.as_ptr(),
          (mem::size_of::<Point>() * self.points.len()) as usize
       )
    }
@@ -156,12 +808,64 @@ type Color = (f32, f32, f32, f32);
 #[derive(Debug, Clone)]
 pub(crate) enum Fill {
    SolidColor(Color),
-   LinearGradient(Point, Point, Color, Color),
+   LinearGradient(Point, Point, Gradient),
+   RadialGradient(Point, f32, Point, f32, Gradient), // (begin center, begin radius, end center, end radius, gradient)
+   ConicGradient(Point, f32, Gradient), // (center, start angle, gradient)
    Image(Rc<Image>, Point, f32, f32),
+   Pattern(Pattern),
+}
+
+
+impl Fill {
+   // Whether two fills paint exactly the same colors. Images are only the same if they're the same `Rc`.
+   pub(crate) fn same_as(&self, other: &Fill) -> bool {
+      match (self, other) {
+         (&Fill::SolidColor(a), &Fill::SolidColor(b)) => a == b,
+         (&Fill::LinearGradient(a_begin, a_end, ref a), &Fill::LinearGradient(b_begin, b_end, ref b)) => {
+            a_begin == b_begin && a_end == b_end && a == b
+         },
+         (&Fill::RadialGradient(a_begin, a_begin_radius, a_end, a_end_radius, ref a), &Fill::RadialGradient(b_begin, b_begin_radius, b_end, b_end_radius, ref b)) => {
+            a_begin == b_begin && a_begin_radius == b_begin_radius && a_end == b_end && a_end_radius == b_end_radius && a == b
+         },
+         (&Fill::ConicGradient(a_center, a_angle, ref a), &Fill::ConicGradient(b_center, b_angle, ref b)) => {
+            a_center == b_center && a_angle == b_angle && a == b
+         },
+         (&Fill::Image(ref a, a_location, a_width, a_height), &Fill::Image(ref b, b_location, b_width, b_height)) => {
+            Rc::ptr_eq(a, b) && a_location == b_location && a_width == b_width && a_height == b_height
+         },
+         (&Fill::Pattern(ref a), &Fill::Pattern(ref b)) => {
+            Rc::ptr_eq(a.image(), b.image()) && a.repetition() == b.repetition() && a.pattern_transform() == b.pattern_transform()
+         },
+         _ => false,
+      }
+   }
+}
+
+/// How a fill decides whether a point is inside a shape made of several, possibly overlapping, subpaths
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FillRule {
+   /// Inside if the subpaths wind around the point more times in one direction than the other.
+   /// A hole needs to be drawn in the opposite direction to its surroundings.
+   NonZero,
+   /// Inside if the point is surrounded an odd number of times, whatever the direction
+   EvenOdd,
+}
+
+
+// A run of points in the canvas's point buffer
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Subpath {
+  pub index: usize,
+  pub length: usize,
+  pub closed: bool,
+  pub convex: bool, // Worked out once the subpath is filled. Convex subpaths can skip the stencil buffer.
 }
 
 #[derive(Debug)]
 pub(crate) struct Figure {
   pub fill: Fill,
-  pub paths: Vec<(usize, usize)> // (index, length)
+  pub paths: Vec<Subpath>,
+  pub text: Vec<TextRun>,
+  pub transform: Matrix, // The canvas transform when the figure was filled. Fill coordinates are relative to it.
+  pub fill_rule: FillRule,
 }
\ No newline at end of file
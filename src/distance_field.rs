@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use rusttype::{Contour, PositionedGlyph, Segment};
+use super::geometry;
+use super::geometry::{Point, Rect};
+
+
+// Glyphs are put in the atlas this many pixels tall, whatever size they're drawn at
+pub(crate) const FIELD_SIZE: f32 = 48.0;
+
+// How far, in atlas pixels, the field reaches out from either side of an outline
+pub(crate) const FIELD_SPREAD: f32 = 6.0;
+
+const INITIAL_ATLAS_SIZE: u32 = 512;
+const MAX_ATLAS_SIZE: u32 = 4096;
+
+// How far flattened curves may stray from the outline, in atlas pixels
+const FLATTENING_TOLERANCE: f32 = 0.05;
+
+
+// Where a glyph's distance field is in the atlas
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct FieldGlyph {
+   pub atlas: Rect, // In atlas pixels
+   pub bounds: Rect, // Around the glyph's origin, y down, in pixels of a glyph FIELD_SIZE tall
+}
+
+
+impl FieldGlyph {
+   // Where the field goes around a glyph laid out at any size, in the coordinates it was laid out in
+   pub fn placed(&self, glyph: &PositionedGlyph) -> Rect {
+      let scale = glyph.scale().y / FIELD_SIZE;
+      let position = glyph.position();
+
+      Rect::new(
+         position.x + self.bounds.x * scale,
+         position.y + self.bounds.y * scale,
+         self.bounds.width * scale,
+         self.bounds.height * scale,
+      )
+   }
+}
+
+
+// Signed distance fields of every glyph drawn so far, packed into rows of a square,
+// single channel image. A value of 0.5 is on the outline, higher is inside and lower outside,
+// reaching 0 and 1 at FIELD_SPREAD pixels away.
+pub(crate) struct DistanceFieldAtlas {
+   pub size: u32,
+   pub pixels: Vec<u8>, // size × size, top row first
+   pub resized: bool, // Whether the atlas has grown since it was last uploaded, so the texture needs making again
+   pub dirty: Option<(u32, u32, u32, u32)>, // Left, top, right and bottom of what's changed since the last upload
+   glyphs: HashMap<u32, Option<FieldGlyph>>, // By glyph id. None for glyphs without a shape.
+   row_x: u32,
+   row_y: u32,
+   row_height: u32,
+}
+
+
+
+impl DistanceFieldAtlas {
+   pub fn new() -> DistanceFieldAtlas {
+      DistanceFieldAtlas {
+         size: INITIAL_ATLAS_SIZE,
+         pixels: vec![0; INITIAL_ATLAS_SIZE as usize * INITIAL_ATLAS_SIZE as usize],
+         resized: true,
+         dirty: None,
+         glyphs: HashMap::new(),
+         row_x: 0,
+         row_y: 0,
+         row_height: 0,
+      }
+   }
+
+
+   // The distance field for the glyph with id `id`, generated from the contours `shape` gives,
+   // at FIELD_SIZE, the first time it's asked for. None for glyphs with no shape, like a space,
+   // and glyphs that don't fit even in the largest atlas.
+   pub fn glyph<F: FnOnce() -> Option<Vec<Contour>>>(&mut self, id: u32, shape: F) -> Option<FieldGlyph> {
+      if let Some(&glyph) = self.glyphs.get(&id) {
+         return glyph;
+      }
+
+      let glyph = shape().and_then(|contours| self.add(&contours));
+      self.glyphs.insert(id, glyph);
+      glyph
+   }
+}
+
+
+
+impl DistanceFieldAtlas {
+   fn add(&mut self, contours: &[Contour]) -> Option<FieldGlyph> {
+      let edges = edges(contours);
+
+      if edges.len() == 0 {
+         return None;
+      }
+
+      // The field covers the outline and as far out as it reaches
+      let (mut min, mut max) = (edges[0].0, edges[0].0);
+      for &(point, _) in &edges {
+         min = Point::new(min.x.min(point.x), min.y.min(point.y));
+         max = Point::new(max.x.max(point.x), max.y.max(point.y));
+      }
+
+      let left = (min.x - FIELD_SPREAD).floor();
+      let top = (min.y - FIELD_SPREAD).floor();
+      let width = ((max.x + FIELD_SPREAD).ceil() - left) as u32;
+      let height = ((max.y + FIELD_SPREAD).ceil() - top) as u32;
+
+      let (x, y) = self.allocate(width, height)?;
+
+      for row in 0..height {
+         for column in 0..width {
+            let center = Point::new(left + column as f32 + 0.5, top + row as f32 + 0.5);
+            let distance = signed_distance(&edges, center);
+            let value = (0.5 + distance / (2.0 * FIELD_SPREAD)).max(0.0).min(1.0);
+            self.pixels[((y + row) * self.size + x + column) as usize] = (value * 255.0 + 0.5) as u8;
+         }
+      }
+
+      self.dirty = Some(match self.dirty {
+         Some((left, top, right, bottom)) => (left.min(x), top.min(y), right.max(x + width), bottom.max(y + height)),
+         None => (x, y, x + width, y + height),
+      });
+
+      Some(FieldGlyph {
+         atlas: Rect::new(x as f32, y as f32, width as f32, height as f32),
+         bounds: Rect::new(left, top, width as f32, height as f32),
+      })
+   }
+
+
+   // Find room for a field, growing the atlas if it's full. Fields are a pixel apart so they don't bleed into each other.
+   fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+      loop {
+         if self.row_x + width > self.size {
+            self.row_x = 0;
+            self.row_y += self.row_height + 1;
+            self.row_height = 0;
+         }
+
+         if self.row_x + width <= self.size && self.row_y + height <= self.size {
+            let position = (self.row_x, self.row_y);
+            self.row_x += width + 1;
+            self.row_height = self.row_height.max(height);
+            return Some(position);
+         }
+
+         if self.size >= MAX_ATLAS_SIZE {
+            return None;
+         }
+
+         // Double the size, keeping everything already there where it is
+         let size = self.size * 2;
+         let mut pixels = vec![0; size as usize * size as usize];
+         for (row, old_row) in self.pixels.chunks(self.size as usize).enumerate() {
+            pixels[row * size as usize..row * size as usize + old_row.len()].copy_from_slice(old_row);
+         }
+
+         self.size = size;
+         self.pixels = pixels;
+         self.resized = true;
+      }
+   }
+}
+
+
+
+// The straight edges of a glyph's outline, flipped to point y down. Each segment is flattened
+// on its own since rusttype's contours don't always join up: it puts the first two in one.
+fn edges(contours: &[Contour]) -> Vec<(Point, Point)> {
+   let flip = |point: ::rusttype::Point<f32>| Point::new(point.x, -point.y);
+   let mut edges = vec![];
+
+   for segment in contours.iter().flat_map(|contour| contour.segments.iter()) {
+      match *segment {
+         Segment::Line(ref line) => edges.push((flip(line.p[0]), flip(line.p[1]))),
+         Segment::Curve(ref curve) => {
+            let mut points = vec![flip(curve.p[0])];
+            geometry::flatten_quadratic(points[0], flip(curve.p[1]), flip(curve.p[2]), FLATTENING_TOLERANCE, &mut points);
+            edges.extend(points.windows(2).map(|pair| (pair[0], pair[1])));
+         }
+      }
+   }
+
+   edges
+}
+
+
+// How far a point is from the nearest edge, positive inside the outline by the non-zero rule
+fn signed_distance(edges: &[(Point, Point)], point: Point) -> f32 {
+   let mut nearest = ::std::f32::INFINITY;
+   let mut winding = 0;
+
+   for &(start, end) in edges {
+      let (edge_x, edge_y) = (end.x - start.x, end.y - start.y);
+      let (to_point_x, to_point_y) = (point.x - start.x, point.y - start.y);
+      let length_squared = edge_x * edge_x + edge_y * edge_y;
+
+      let along = if length_squared > 0.0 {
+         ((to_point_x * edge_x + to_point_y * edge_y) / length_squared).max(0.0).min(1.0)
+      } else {
+         0.0
+      };
+
+      let (offset_x, offset_y) = (to_point_x - along * edge_x, to_point_y - along * edge_y);
+      nearest = nearest.min(offset_x * offset_x + offset_y * offset_y);
+
+      // Count crossings of a ray going right from the point
+      let cross = edge_x * to_point_y - edge_y * to_point_x;
+      if start.y <= point.y && end.y > point.y && cross > 0.0 {
+         winding += 1;
+      } else if end.y <= point.y && start.y > point.y && cross < 0.0 {
+         winding -= 1;
+      }
+   }
+
+   if winding != 0 { nearest.sqrt() } else { -nearest.sqrt() }
+}
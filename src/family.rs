@@ -0,0 +1,93 @@
+use std::rc::Rc;
+use super::{Font, TextMetrics};
+use super::font::missing_characters;
+
+
+/// Fonts to draw text with, in order of preference. Each character is drawn with the first font
+/// that has a glyph for it, so a Latin font can fall back on, say, CJK and symbol fonts.
+/// Draw with `Canvas::fill_text_family` or `Canvas::text_path_family`.
+#[derive(Debug, Clone)]
+pub struct FontFamily {
+   fonts: Vec<Rc<Font>>,
+}
+
+
+
+impl FontFamily {
+   pub fn new(font: Rc<Font>) -> FontFamily {
+      FontFamily {
+         fonts: vec![font],
+      }
+   }
+
+
+   /// Add a font for the characters the fonts before it don't have
+   pub fn fallback(mut self, font: Rc<Font>) -> Self {
+      self.fonts.push(font);
+      self
+   }
+
+
+   pub fn fonts(&self) -> &[Rc<Font>] {
+      &self.fonts
+   }
+
+
+   /// The first font with a glyph for a character, if any of them have one
+   pub fn font_for(&self, character: char) -> Option<&Rc<Font>> {
+      self.fonts.iter().find(|font| font.has_glyph(character))
+   }
+
+
+   /// The characters in `text` none of the fonts have glyphs for, each listed once.
+   /// They're drawn with the first font's missing glyph symbol. Control characters are left out.
+   pub fn missing_glyphs(&self, text: &str) -> Vec<char> {
+      missing_characters(text, |character| self.font_for(character).is_some())
+   }
+
+
+   /// Measure a line of text the way `Canvas::fill_text_family` lays it out.
+   /// The ascent, descent and line gap are the largest of the fonts it uses.
+   pub fn measure(&self, text: &str, size: f32) -> TextMetrics {
+      let mut metrics = self.fonts[0].measure("", size);
+
+      for (font, run) in self.runs(text) {
+         let run_metrics = font.measure(&run, size);
+         let start = metrics.width;
+
+         metrics.positions.extend(run_metrics.positions.iter().map(|position| start + position));
+         metrics.width += run_metrics.width;
+         metrics.ascent = metrics.ascent.max(run_metrics.ascent);
+         metrics.descent = metrics.descent.max(run_metrics.descent);
+         metrics.line_gap = metrics.line_gap.max(run_metrics.line_gap);
+      }
+
+      metrics
+   }
+
+
+   // Split text into runs of characters drawn with the same font. Characters none of the fonts
+   // have go to the first one. Whitespace stays with the run it's in, or the first font if it starts the text.
+   pub(crate) fn runs(&self, text: &str) -> Vec<(Rc<Font>, String)> {
+      let mut runs: Vec<(Rc<Font>, String)> = vec![];
+
+      for character in text.chars() {
+         let font = if character.is_whitespace() { None } else { Some(self.font_for(character).unwrap_or(&self.fonts[0])) };
+
+         let continues = match (runs.last(), font) {
+            (Some(&(ref current, _)), Some(font)) => Rc::ptr_eq(current, font),
+            (Some(_), None) => true,
+            (None, _) => false,
+         };
+
+         if continues {
+            runs.last_mut().unwrap().1.push(character);
+         } else {
+            let font = font.unwrap_or(&self.fonts[0]).clone();
+            runs.push((font, character.to_string()));
+         }
+      }
+
+      runs
+   }
+}
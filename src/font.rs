@@ -1,63 +1,383 @@
 use rusttype;
+use rusttype::{PositionedGlyph, Scale};
+use rusttype::gpu_cache::Cache;
 use gl;
-use super::image::Image;
-use std;
+use gl::types::*;
 
-pub struct Font<'a> {
-   font: rusttype::Font<'a>,
-   cache: rusttype::gpu_cache::Cache,
-   texture: Image,
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::rc::Rc;
+use std::cell::{Cell, Ref, RefCell};
+use super::geometry::{Matrix, Point, Rect};
+use super::distance_field::{DistanceFieldAtlas, FieldGlyph, FIELD_SIZE};
+
+
+// The glyph atlas starts out this many pixels across and doubles whenever it runs out of room
+const INITIAL_ATLAS_SIZE: u32 = 512;
+const MAX_ATLAS_SIZE: u32 = 4096;
+
+// How far, in pixels, a glyph in the atlas may be from the size and subpixel position
+// asked for and still be used instead of rasterizing it again
+const ATLAS_TOLERANCE: f32 = 0.1;
+
+
+/// A TrueType or OpenType font for drawing text with `Canvas::fill_text`.
+///
+/// Glyphs are rasterized the first time they're drawn with a `Renderer` and kept in a
+/// texture, the glyph atlas, which is shared by all text in the font.
+pub struct Font {
+   font: rusttype::Font<'static>,
+   atlas: RefCell<Cache>,
+   atlas_size: Cell<u32>,
+   texture_id: Cell<GLuint>, // 0 until text in the font has been drawn with a `Renderer`
+   distance_fields: RefCell<DistanceFieldAtlas>,
+   distance_field_texture_id: Cell<GLuint>, // 0 until text has been drawn with `TextRendering::DistanceField`
 }
 
 
-impl<'a> Font<'a> {
-   fn new(font_data: &[u8]) -> Font {
-      let font = rusttype::FontCollection::from_bytes(font_data as &[u8]).into_font().unwrap();
-      let mut font_cache = rusttype::gpu_cache::Cache::new(512, 512, 0.1, 0.1);
+/// How renderers draw text added with `Canvas::fill_text`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextRendering {
+   /// Rasterize glyphs at exactly the size they're drawn. The sharpest text, but every new size
+   /// or zoom level rasterizes the glyphs again.
+   Bitmap,
+   /// Draw every size from a single signed distance field of each glyph, so text stays sharp
+   /// when zooming or rotating without rasterizing anything again. Corners come out slightly rounded.
+   DistanceField,
+}
 
 
-      let texture_id = unsafe {
-         let mut texture_id = 0;
-         gl::GenTextures(1, &mut texture_id);
-         gl::BindTexture(gl::TEXTURE_2D, texture_id);
-         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
-         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
-         gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA as i32, 512, 512, 0, gl::RGBA, gl::UNSIGNED_BYTE, std::ptr::null());
-         texture_id
-      };
-
-
-
-
-      let glyph = font.glyph('A').unwrap().scaled(rusttype::Scale::uniform(24.0)).positioned(rusttype::point(0.0, 0.0));
-      println!("beginqueu");
-      font_cache.queue_glyph(0, glyph);
-      println!("queued");
-      font_cache.cache_queued(|rect, data| {
-         println!("caching");
-         gl::TexSubImage2D(
-            texture_id,
-            0,
-            rect.min.x as i32,
-            rect.min.y as i32,
-            rect.width() as i32,
-            rect.height() as i32,
-            gl::RGB,
-            gl::BYTE,
-            std::ptr::null()
-         );
-      });
-      println!("Done caching");
-
-      println!("{:?}", font_cache.rect_for(0, 'A'));
-
-      Font {
-         cache: font_cache,
+impl Drop for Font {
+   fn drop(&mut self) {
+      if self.texture_id.get() != 0 {
+         unsafe {
+            gl::DeleteTextures(1, &self.texture_id.get());
+         }
+      }
+
+      if self.distance_field_texture_id.get() != 0 {
+         unsafe { gl::DeleteTextures(1, &self.distance_field_texture_id.get()); }
+      }
+   }
+}
+
+
+impl fmt::Debug for Font {
+   fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+      write!(formatter, "Font {{ glyph_count: {}, atlas_size: {} }}", self.font.glyph_count(), self.atlas_size.get())
+   }
+}
+
+
+
+impl Font {
+   /// Load a font from a .ttf or .otf file, or the first font in a .ttc collection
+   pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Rc<Font>> {
+      Font::new_at(path, 0)
+   }
+
+
+   /// Load the font at `index` in a .ttc collection. Index 0 also loads a .ttf or .otf file.
+   pub fn new_at<P: AsRef<Path>>(path: P, index: usize) -> io::Result<Rc<Font>> {
+      Font::from_bytes_at(read_file(path)?, index)
+   }
+
+
+   /// Load every font in a .ttc collection, in order, or the one in a .ttf or .otf file
+   pub fn collection<P: AsRef<Path>>(path: P) -> io::Result<Vec<Rc<Font>>> {
+      Font::collection_from_bytes(read_file(path)?)
+   }
+
+
+   /// Load a font from the contents of a .ttf or .otf file, or the first font in a .ttc collection
+   pub fn from_bytes(data: Vec<u8>) -> io::Result<Rc<Font>> {
+      Font::from_bytes_at(data, 0)
+   }
+
+
+   /// Load the font at `index` in the contents of a .ttc collection
+   pub fn from_bytes_at(data: Vec<u8>, index: usize) -> io::Result<Rc<Font>> {
+      // rusttype turns the index into an i32, and one that wraps round to negative makes it panic
+      if index > ::std::i32::MAX as usize {
+         return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("no font at index {} in a collection", index)));
+      }
+
+      let font = rusttype::FontCollection::from_bytes(data).font_at(index)
+         .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("no font at index {} that can be read", index)))?;
+
+      Ok(Font::from_face(font))
+   }
+
+
+   /// Load every font in the contents of a .ttc collection
+   pub fn collection_from_bytes(data: Vec<u8>) -> io::Result<Vec<Rc<Font>>> {
+      let fonts: Vec<Rc<Font>> = rusttype::FontCollection::from_bytes(data).into_fonts().map(Font::from_face).collect();
+
+      if fonts.len() == 0 {
+         return Err(io::Error::new(io::ErrorKind::InvalidData, "not a font that can be read"));
+      }
+
+      Ok(fonts)
+   }
+
+
+   /// Whether the font has a glyph for a character. Characters it doesn't have are drawn
+   /// with the font's missing glyph symbol, which is often an empty box or nothing at all.
+   pub fn has_glyph(&self, character: char) -> bool {
+      // Glyph 0 is the missing glyph symbol
+      self.font.glyph(character).map_or(false, |glyph| glyph.id().0 != 0)
+   }
+
+
+   /// The characters in `text` the font doesn't have glyphs for, each listed once. Control characters are left out.
+   pub fn missing_glyphs(&self, text: &str) -> Vec<char> {
+      missing_characters(text, |character| self.has_glyph(character))
+   }
+
+
+   /// Measure a line of text `size` units tall, laid out the same way as `Canvas::fill_text`
+   pub fn measure(&self, text: &str, size: f32) -> TextMetrics {
+      let scale = Scale::uniform(size);
+      let v_metrics = self.font.v_metrics(scale);
+      let glyphs: Vec<PositionedGlyph> = self.font.layout(text, scale, rusttype::point(0.0, 0.0)).collect();
+
+      TextMetrics {
+         width: glyphs.last().map_or(0.0, |glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width),
+         ascent: v_metrics.ascent,
+         descent: -v_metrics.descent,
+         line_gap: v_metrics.line_gap,
+         positions: glyphs.iter().map(|glyph| glyph.position().x).collect(),
+      }
+   }
+}
+
+
+
+/// The size of a line of text, from `Font::measure`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextMetrics {
+   /// How far along the text goes, which is where more text would start
+   pub width: f32,
+   /// How far the font reaches above the baseline
+   pub ascent: f32,
+   /// How far the font reaches below the baseline
+   pub descent: f32,
+   /// The space the font suggests leaving between one line's descent and the next one's ascent
+   pub line_gap: f32,
+   /// Where each character starts along the baseline, with kerning applied
+   pub positions: Vec<f32>,
+}
+
+
+
+impl Font {
+   fn from_face(font: rusttype::Font<'static>) -> Rc<Font> {
+      Rc::new(Font {
          font: font,
+         atlas: RefCell::new(Cache::new(INITIAL_ATLAS_SIZE, INITIAL_ATLAS_SIZE, ATLAS_TOLERANCE, ATLAS_TOLERANCE)),
+         atlas_size: Cell::new(INITIAL_ATLAS_SIZE),
+         texture_id: Cell::new(0),
+         distance_fields: RefCell::new(DistanceFieldAtlas::new()),
+         distance_field_texture_id: Cell::new(0),
+      })
+   }
+
+
+   // Lay out a line of text `size` pixels tall with its baseline starting at `start`
+   pub(crate) fn layout(&self, text: &str, size: f32, start: Point) -> Vec<PositionedGlyph<'static>> {
+      self.font.layout(text, Scale::uniform(size), rusttype::point(start.x, start.y))
+         .map(|glyph| glyph.standalone())
+         .collect()
+   }
+
+
+   // The contours of a line of text `size` units tall, with its baseline starting at the origin.
+   // Like the font itself they're y-up, so they need flipping to go on a canvas.
+   pub(crate) fn outline(&self, text: &str, size: f32) -> Vec<rusttype::Contour> {
+      self.font.layout(text, Scale::uniform(size), rusttype::point(0.0, 0.0))
+         .filter_map(|glyph| glyph.shape())
+         .flat_map(|contours| contours)
+         .collect()
+   }
+
+
+   // Make sure all of `glyphs` are in the glyph atlas, growing it if they don't fit,
+   // and return the atlas texture. Glyphs that can't fit even in the largest atlas are left out.
+   // Requires a current OpenGL context.
+   pub(crate) fn cache_glyphs(&self, glyphs: &[PositionedGlyph<'static>]) -> GLuint {
+      let mut atlas = self.atlas.borrow_mut();
+
+      loop {
+         if self.texture_id.get() == 0 {
+            self.texture_id.set(create_atlas_texture(self.atlas_size.get()));
+         }
+
+         let texture_id = self.texture_id.get();
+
+         for glyph in glyphs {
+            atlas.queue_glyph(0, glyph.clone());
+         }
+
+         let result = atlas.cache_queued(|rect, pixels| unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, texture_id);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexSubImage2D(
+               gl::TEXTURE_2D, 0,
+               rect.min.x as i32, rect.min.y as i32,
+               rect.width() as i32, rect.height() as i32,
+               gl::RED, gl::UNSIGNED_BYTE,
+               pixels.as_ptr() as *const _
+            );
+         });
+
+         atlas.clear_queue();
+
+         if result.is_ok() || self.atlas_size.get() >= MAX_ATLAS_SIZE {
+            return texture_id;
+         }
+
+         // Start over with an atlas twice the size
+         unsafe { gl::DeleteTextures(1, &texture_id); }
+         self.texture_id.set(0);
+         self.atlas_size.set(self.atlas_size.get() * 2);
+         *atlas = Cache::new(self.atlas_size.get(), self.atlas_size.get(), ATLAS_TOLERANCE, ATLAS_TOLERANCE);
+      }
+   }
+
+
+   // Where a glyph cached by `cache_glyphs` is in the atlas, in texture coordinates,
+   // and the pixels it covers in the coordinates it was laid out in.
+   // None if the glyph has no shape, like a space, or isn't in the atlas.
+   pub(crate) fn atlas_rect(&self, glyph: &PositionedGlyph) -> Option<(Rect, Rect)> {
+      match self.atlas.borrow().rect_for(0, glyph) {
+         Ok(Some((texture, pixels))) => Some((
+            Rect::new(texture.min.x, texture.min.y, texture.width(), texture.height()),
+            Rect::new(pixels.min.x as f32, pixels.min.y as f32, pixels.width() as f32, pixels.height() as f32),
+         )),
+         _ => None,
       }
    }
-      // let font_data = include_bytes!("NotoSerif-Regular.ttf");
+}
+
+
+
+impl Font {
+   // The distance field of a glyph, generated the first time it's needed
+   pub(crate) fn distance_field(&self, glyph: &PositionedGlyph) -> Option<FieldGlyph> {
+      let font = &self.font;
+      let id = glyph.id();
+
+      self.distance_fields.borrow_mut().glyph(id.0, || {
+         font.glyph(id).and_then(|glyph| glyph.scaled(Scale::uniform(FIELD_SIZE)).shape())
+      })
+   }
+
+
+   pub(crate) fn distance_field_atlas(&self) -> Ref<'_, DistanceFieldAtlas> {
+      self.distance_fields.borrow()
+   }
+
+
+   // Upload any distance fields generated since the last call and return the texture
+   // along with how many pixels across it is. Only what's changed is uploaded, unless
+   // the atlas has grown. Requires a current OpenGL context.
+   pub(crate) fn upload_distance_fields(&self) -> (GLuint, u32) {
+      let mut atlas = self.distance_fields.borrow_mut();
 
-}
\ No newline at end of file
+      if self.distance_field_texture_id.get() == 0 {
+         self.distance_field_texture_id.set(create_atlas_texture(atlas.size));
+         atlas.resized = true;
+      }
+
+      let texture_id = self.distance_field_texture_id.get();
+
+      unsafe {
+         gl::BindTexture(gl::TEXTURE_2D, texture_id);
+         gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+
+         if atlas.resized {
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R8 as i32, atlas.size as i32, atlas.size as i32, 0, gl::RED, gl::UNSIGNED_BYTE, atlas.pixels.as_ptr() as *const _);
+         } else if let Some((left, top, right, bottom)) = atlas.dirty {
+            // Rows of the changed rectangle are a whole atlas apart in memory
+            let start = (top * atlas.size + left) as usize;
+            gl::PixelStorei(gl::UNPACK_ROW_LENGTH, atlas.size as i32);
+            gl::TexSubImage2D(
+               gl::TEXTURE_2D, 0,
+               left as i32, top as i32,
+               (right - left) as i32, (bottom - top) as i32,
+               gl::RED, gl::UNSIGNED_BYTE,
+               atlas.pixels[start..].as_ptr() as *const _
+            );
+            gl::PixelStorei(gl::UNPACK_ROW_LENGTH, 0);
+         }
+      }
+
+      atlas.resized = false;
+      atlas.dirty = None;
+
+      (texture_id, atlas.size)
+   }
+}
+
+
+
+// A line of text waiting to be filled. The glyphs are laid out in pixels of their
+// own, which `transform` maps into canvas coordinates.
+#[derive(Clone)]
+pub(crate) struct TextRun {
+   pub font: Rc<Font>,
+   pub glyphs: Vec<PositionedGlyph<'static>>,
+   pub transform: Matrix,
+}
+
+
+impl fmt::Debug for TextRun {
+   fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+      write!(formatter, "TextRun {{ font: {:?}, glyphs: {}, transform: {:?} }}", self.font, self.glyphs.len(), self.transform)
+   }
+}
+
+
+
+// The characters in `text` that `has_glyph` says there's no glyph for, each listed once, leaving out control characters
+pub(crate) fn missing_characters<F: Fn(char) -> bool>(text: &str, has_glyph: F) -> Vec<char> {
+   let mut missing = vec![];
+
+   for character in text.chars() {
+      if !character.is_control() && !has_glyph(character) && !missing.contains(&character) {
+         missing.push(character);
+      }
+   }
+
+   missing
+}
+
+
+fn read_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+   let mut data = vec![];
+   File::open(path)?.read_to_end(&mut data)?;
+   Ok(data)
+}
+
+
+
+// A blank single channel texture for the glyph atlas
+fn create_atlas_texture(size: u32) -> GLuint {
+   let blank = vec![0u8; size as usize * size as usize];
+
+   unsafe {
+      let mut texture_id = 0;
+      gl::GenTextures(1, &mut texture_id);
+      gl::BindTexture(gl::TEXTURE_2D, texture_id);
+      gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+      gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+      gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+      gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+      gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+      gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R8 as i32, size as i32, size as i32, 0, gl::RED, gl::UNSIGNED_BYTE, blank.as_ptr() as *const _);
+      texture_id
+   }
+}
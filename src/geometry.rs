@@ -1,5 +1,8 @@
-#[derive(Debug, Copy, Clone)]
-pub(crate) struct Point {
+use std::f32::consts;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(C)] // Uploaded to the GPU as pairs of floats
+pub struct Point {
    pub x: f32,
    pub y: f32,
 }
@@ -11,14 +14,61 @@ impl Point {
 }
 
 
+/// An axis aligned rectangle
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rect {
+   pub x: f32,
+   pub y: f32,
+   pub width: f32,
+   pub height: f32,
+}
+
+impl Rect {
+   pub fn new(x: f32, y: f32, width: f32, height: f32) -> Rect {
+      Rect {x: x, y: y, width: width, height: height}
+   }
+}
+
+
+/// The pieces a matrix can be split into by `Matrix::decompose`.
+/// Applying them in the order translate, rotate, skew_x, scale rebuilds the matrix.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Decomposition {
+   pub translation: Point,
+   /// Radians
+   pub rotation: f32,
+   /// Horizontal skew in radians, as passed to `Matrix::from_skew_x`
+   pub skew: f32,
+   /// A negative y scale means the matrix mirrors
+   pub scale: Point,
+}
+
+
+/// A 2D affine transformation.
+/// Multiply it with a `Point` to transform the point, or with another matrix to combine the two.
 // 0 1 2
 // 3 4 5
 // 6 7 8
-#[derive(Debug, Copy, Clone)]
-pub(crate) struct Matrix ([f32; 9]);
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Matrix ([f32; 9]);
 
 
 impl Matrix {
+   /// Build a matrix from its six affine components, in the same order as the HTML canvas's `transform`:
+   ///
+   /// ```text
+   /// a c e
+   /// b d f
+   /// 0 0 1
+   /// ```
+   pub fn new(a: f32, b: f32, c: f32, d: f32, e: f32, f: f32) -> Matrix {
+      Matrix ([
+         a,   c,   e,
+         b,   d,   f,
+         0.0, 0.0, 1.0,
+      ])
+   }
+
    pub fn identity() -> Matrix {
       Matrix ([
          1.0, 0.0, 0.0,
@@ -42,6 +92,133 @@ impl Matrix {
          0.0, 0.0, 1.0,
       ])
    }
+
+   pub fn from_scale(x: f32, y: f32) -> Matrix {
+      Matrix ([
+         x,   0.0, 0.0,
+         0.0, y,   0.0,
+         0.0, 0.0, 1.0,
+      ])
+   }
+
+   /// Slant vertical lines by `angle` radians
+   pub fn from_skew_x(angle: f32) -> Matrix {
+      Matrix ([
+         1.0, angle.tan(), 0.0,
+         0.0, 1.0,         0.0,
+         0.0, 0.0,         1.0,
+      ])
+   }
+
+   /// Slant horizontal lines by `angle` radians
+   pub fn from_skew_y(angle: f32) -> Matrix {
+      Matrix ([
+         1.0,         0.0, 0.0,
+         angle.tan(), 1.0, 0.0,
+         0.0,         0.0, 1.0,
+      ])
+   }
+
+
+   /// The six affine components in the order taken by `Matrix::new`
+   pub fn to_affine(&self) -> [f32; 6] {
+      let m = self.0;
+      [m[0], m[3], m[1], m[4], m[2], m[5]]
+   }
+
+
+   /// How much the matrix scales areas. Negative when it mirrors, zero when it can't be inverted.
+   pub fn determinant(&self) -> f32 {
+      let m = self.0;
+      m[0] * m[4] - m[1] * m[3]
+   }
+
+
+   /// The matrix that undoes this one, or None if it squashes everything onto a line or point
+   pub fn inverse(&self) -> Option<Matrix> {
+      let m = self.0;
+      let determinant = self.determinant();
+
+      if determinant == 0.0 || !determinant.is_finite() {
+         return None;
+      }
+
+      Some(Matrix ([
+          m[4] / determinant, -m[1] / determinant, (m[1] * m[5] - m[4] * m[2]) / determinant,
+         -m[3] / determinant,  m[0] / determinant, (m[3] * m[2] - m[0] * m[5]) / determinant,
+          0.0,                 0.0,                1.0,
+      ]))
+   }
+
+
+   /// Split the matrix into translation, rotation, skew and scale.
+   /// A matrix that squashes everything onto a line or point decomposes into zeros.
+   pub fn decompose(&self) -> Decomposition {
+      let m = self.0;
+      let (a, b, c, d) = (m[0], m[3], m[1], m[4]);
+      let translation = Point::new(m[2], m[5]);
+
+      let scale_x = (a * a + b * b).sqrt();
+      let determinant = self.determinant();
+
+      if scale_x == 0.0 || determinant == 0.0 {
+         return Decomposition {translation: translation, rotation: 0.0, skew: 0.0, scale: Point::new(0.0, 0.0)};
+      }
+
+      let scale_y = determinant / scale_x;
+
+      Decomposition {
+         translation: translation,
+         rotation: b.atan2(a),
+         skew: ((a * c + b * d) / (scale_x * scale_y)).atan(),
+         scale: Point::new(scale_x, scale_y),
+      }
+   }
+
+
+   pub fn map_point(&self, point: Point) -> Point {
+      *self * point
+   }
+
+
+   /// The smallest axis aligned rectangle containing the transformed rectangle
+   pub fn map_rect(&self, rect: Rect) -> Rect {
+      let corners = [
+         self.map_point(Point::new(rect.x, rect.y)),
+         self.map_point(Point::new(rect.x + rect.width, rect.y)),
+         self.map_point(Point::new(rect.x + rect.width, rect.y + rect.height)),
+         self.map_point(Point::new(rect.x, rect.y + rect.height)),
+      ];
+
+      let min_x = corners.iter().fold(::std::f32::INFINITY, |min, point| min.min(point.x));
+      let min_y = corners.iter().fold(::std::f32::INFINITY, |min, point| min.min(point.y));
+      let max_x = corners.iter().fold(::std::f32::NEG_INFINITY, |max, point| max.max(point.x));
+      let max_y = corners.iter().fold(::std::f32::NEG_INFINITY, |max, point| max.max(point.y));
+
+      Rect::new(min_x, min_y, max_x - min_x, max_y - min_y)
+   }
+
+
+   // The most the matrix stretches any line, which is its largest singular value
+   pub(crate) fn max_scale(&self) -> f32 {
+      let m = self.0;
+      let sum = m[0] * m[0] + m[1] * m[1] + m[3] * m[3] + m[4] * m[4];
+      let determinant = self.determinant();
+
+      ((sum + (sum * sum - 4.0 * determinant * determinant).max(0.0).sqrt()) / 2.0).sqrt()
+   }
+
+
+   /// True if every component differs by no more than `epsilon`
+   pub fn approx_eq(&self, other: &Matrix, epsilon: f32) -> bool {
+      self.0.iter().zip(other.0.iter()).all(|(a, b)| (a - b).abs() <= epsilon)
+   }
+
+
+   // Row-major matrix elements, as expected by UniformMatrix3fv with transpose set
+   pub(crate) fn as_array(&self) -> &[f32; 9] {
+      &self.0
+   }
 }
 
 
@@ -88,4 +265,101 @@ impl ::std::ops::Mul<Point> for Matrix {
          y: left[3] * right.x + left[4] * right.y + left[5],
       }
    }
-}
\ No newline at end of file
+}
+
+
+
+// Curves are drawn as a series of straight lines. The number of lines is
+// picked with Wang's formula, which bounds the distance between the lines
+// and the curve by `tolerance`. The start point is not added to `output`.
+pub(crate) fn flatten_quadratic(start: Point, control: Point, end: Point, tolerance: f32, output: &mut Vec<Point>) {
+   let dx = start.x - 2.0 * control.x + end.x;
+   let dy = start.y - 2.0 * control.y + end.y;
+   let segments = segment_count((dx * dx + dy * dy).sqrt() * 0.25, tolerance);
+
+   for i in 1..segments + 1 {
+      let t = i as f32 / segments as f32;
+      let u = 1.0 - t;
+
+      output.push(Point::new(
+         u * u * start.x + 2.0 * u * t * control.x + t * t * end.x,
+         u * u * start.y + 2.0 * u * t * control.y + t * t * end.y,
+      ));
+   }
+}
+
+
+pub(crate) fn flatten_cubic(start: Point, control_1: Point, control_2: Point, end: Point, tolerance: f32, output: &mut Vec<Point>) {
+   let dx1 = start.x - 2.0 * control_1.x + control_2.x;
+   let dy1 = start.y - 2.0 * control_1.y + control_2.y;
+   let dx2 = control_1.x - 2.0 * control_2.x + end.x;
+   let dy2 = control_1.y - 2.0 * control_2.y + end.y;
+   let largest = (dx1 * dx1 + dy1 * dy1).sqrt().max((dx2 * dx2 + dy2 * dy2).sqrt());
+   let segments = segment_count(largest * 0.75, tolerance);
+
+   for i in 1..segments + 1 {
+      let t = i as f32 / segments as f32;
+      let u = 1.0 - t;
+
+      output.push(Point::new(
+         u * u * u * start.x + 3.0 * u * u * t * control_1.x + 3.0 * u * t * t * control_2.x + t * t * t * end.x,
+         u * u * u * start.y + 3.0 * u * u * t * control_1.y + 3.0 * u * t * t * control_2.y + t * t * t * end.y,
+      ));
+   }
+}
+
+
+// Wang's formula: n = sqrt(d(d - 1)/8 * M / tolerance), with the constant folded into `bend`
+fn segment_count(bend: f32, tolerance: f32) -> usize {
+   let segments = (bend / tolerance).sqrt().ceil();
+
+   if segments.is_finite() {
+      (segments as usize).max(1).min(1000)
+   } else {
+      1
+   }
+}
+
+
+
+// Whether the polygon through `points`, closed back to the first point, is convex:
+// it never turns back on itself and goes around exactly once. Repeated points
+// and edges that carry straight on are allowed.
+pub(crate) fn is_convex(points: &[Point]) -> bool {
+   // Direction of each edge, leaving out the ones with no length
+   let edges: Vec<Point> = (0..points.len()).filter_map(|i| {
+      let (start, end) = (points[i], points[(i + 1) % points.len()]);
+      let edge = Point::new(end.x - start.x, end.y - start.y);
+      if edge.x != 0.0 || edge.y != 0.0 { Some(edge) } else { None }
+   }).collect();
+
+   if edges.len() < 3 {
+      return false;
+   }
+
+   let mut direction = 0.0;
+   let mut turned = 0.0;
+
+   for (i, &incoming) in edges.iter().enumerate() {
+      let outgoing = edges[(i + 1) % edges.len()];
+      let cross = incoming.x * outgoing.y - incoming.y * outgoing.x;
+      let dot = incoming.x * outgoing.x + incoming.y * outgoing.y;
+
+      // Nearly straight on. Rounding could give the turn either sign.
+      let lengths = (incoming.x * incoming.x + incoming.y * incoming.y).sqrt() * (outgoing.x * outgoing.x + outgoing.y * outgoing.y).sqrt();
+      if cross.abs() <= 1e-6 * lengths {
+         if dot < 0.0 { return false; }
+         continue;
+      }
+
+      // Every turn has to be the same way
+      if cross * direction < 0.0 {
+         return false;
+      }
+      direction = cross;
+      turned += cross.atan2(dot);
+   }
+
+   // Stars turn the same way at every point too, but go around more than once
+   (turned.abs() - 2.0 * consts::PI).abs() < 0.01
+}
@@ -0,0 +1,107 @@
+/// How a gradient is colored before its first stop and after its last one
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Spread {
+   /// Keep using the color of the nearest end
+   Pad,
+   /// Start over from the beginning
+   Repeat,
+   /// Go back the way it came, like a mirror
+   Reflect,
+}
+
+
+/// The colors of a gradient fill. Pass it to `Canvas::fill_linear_gradient`,
+/// `Canvas::fill_radial_gradient` or `Canvas::fill_conic_gradient`.
+///
+/// Offsets run from 0.0, where the gradient begins, to 1.0, where it ends.
+/// Colors are blended between neighboring stops.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+   stops: Vec<(f32, (f32, f32, f32, f32))>, // Sorted by offset
+   spread: Spread,
+}
+
+
+impl Gradient {
+   /// The largest number of stops the renderer will use. Stops past this are ignored.
+   pub const MAX_STOPS: usize = 16;
+
+
+   pub fn new() -> Gradient {
+      Gradient {
+         stops: vec![],
+         spread: Spread::Pad,
+      }
+   }
+
+
+   /// Add a color at `offset`, which is clamped between 0.0 and 1.0.
+   /// Stops at the same offset make a hard edge, in the order they were added.
+   pub fn add_color_stop(mut self, offset: f32, red: f32, green: f32, blue: f32, alpha: f32) -> Self {
+      if self.stops.len() >= Gradient::MAX_STOPS || offset.is_nan() {
+         return self;
+      }
+
+      let offset = offset.max(0.0).min(1.0);
+      let position = self.stops.iter().position(|&(existing, _)| existing > offset).unwrap_or(self.stops.len());
+      self.stops.insert(position, (offset, (red, green, blue, alpha)));
+      self
+   }
+
+
+   /// Choose what happens past the ends of the gradient. Defaults to `Spread::Pad`.
+   pub fn spread(mut self, spread: Spread) -> Self {
+      self.spread = spread;
+      self
+   }
+}
+
+
+
+impl Gradient {
+   pub(crate) fn stops(&self) -> &[(f32, (f32, f32, f32, f32))] {
+      &self.stops
+   }
+
+
+   pub(crate) fn spread_mode(&self) -> Spread {
+      self.spread
+   }
+
+
+   // The color at `position` along the gradient. Matches `gradient_color` in the renderer's fragment shader.
+   pub(crate) fn color_at(&self, position: f32) -> (f32, f32, f32, f32) {
+      if self.stops.len() == 0 || position.is_nan() {
+         return (0.0, 0.0, 0.0, 0.0);
+      }
+
+      let position = match self.spread {
+         Spread::Pad => position,
+         Spread::Repeat => position - position.floor(),
+         Spread::Reflect => 1.0 - (position.rem_euclid(2.0) - 1.0).abs(),
+      }.max(0.0).min(1.0);
+
+      if position <= self.stops[0].0 {
+         return self.stops[0].1;
+      }
+
+      for pair in self.stops.windows(2) {
+         let (before_offset, before) = pair[0];
+         let (after_offset, after) = pair[1];
+
+         if position <= after_offset {
+            let span = after_offset - before_offset;
+            let t = if span > 0.0 { (position - before_offset) / span } else { 1.0 };
+
+            return (
+               before.0 + t * (after.0 - before.0),
+               before.1 + t * (after.1 - before.1),
+               before.2 + t * (after.2 - before.2),
+               before.3 + t * (after.3 - before.3),
+            );
+         }
+      }
+
+      self.stops[self.stops.len() - 1].1
+   }
+}
@@ -3,20 +3,31 @@ use gl;
 use gl::types::*;
 
 use std::path::Path;
-use std::mem;
+use std::ptr;
 use std::rc::Rc;
+use std::cell::Cell;
 
+/// An image that can be used to fill shapes.
+/// The pixels are kept in memory and only copied to the GPU the first time
+/// the image is drawn with a `Renderer`, so images can be loaded without OpenGL.
+///
+/// The image of a `RenderTarget` is the exception: it only exists on the GPU,
+/// so the `SoftwareRenderer` sees it as fully transparent.
 #[derive(Debug)]
 pub struct Image {
-   pub(crate) texture_id: GLuint
+   texture_id: Cell<GLuint>, // 0 until the image has been uploaded
+   width: u32,
+   height: u32,
+   pixels: Vec<u8>, // RGBA, 8 bits per channel, top row first. Empty if the image only exists on the GPU.
 }
 
 
 impl Drop for Image {
    fn drop(&mut self) {
-      unsafe {
-         println!("Dropping Image");
-         gl::DeleteTextures(1, &self.texture_id);
+      if self.texture_id.get() != 0 {
+         unsafe {
+            gl::DeleteTextures(1, &self.texture_id.get());
+         }
       }
    }
 }
@@ -25,21 +36,108 @@ impl Drop for Image {
 impl Image {
    pub fn new<P: AsRef<Path>>(path: P) -> Result<Rc<Image>, imagefmt::Error> {
       let image = imagefmt::read(path, imagefmt::ColFmt::RGBA)?;
+      Image::from_rgba(image.w as u32, image.h as u32, image.buf)
+   }
+
 
-      let texture_id = unsafe {
-         let mut texture_id = 0;
-         gl::GenTextures(1, &mut texture_id);
-         gl::BindTexture(gl::TEXTURE_2D, texture_id);
-         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
-         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
-         gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA as i32, image.w as i32, image.h as i32, 0, gl::RGBA, gl::UNSIGNED_BYTE, mem::transmute(&image.buf[0]));
-         texture_id
-      };
+   /// Create an image from RGBA pixels, 8 bits per channel, starting with the top row
+   pub fn from_rgba(width: u32, height: u32, pixels: Vec<u8>) -> Result<Rc<Image>, imagefmt::Error> {
+      if pixels.len() != width as usize * height as usize * 4 {
+         return Err(imagefmt::Error::InvalidArg("pixel buffer length doesn't match the image size"));
+      }
 
       Ok(Rc::new(Image {
-         texture_id: texture_id
+         texture_id: Cell::new(0),
+         width: width,
+         height: height,
+         pixels: pixels,
       }))
    }
+
+
+   /// Width in pixels
+   pub fn width(&self) -> u32 {
+      self.width
+   }
+
+
+   /// Height in pixels
+   pub fn height(&self) -> u32 {
+      self.height
+   }
+}
+
+
+
+impl Image {
+   // The OpenGL texture holding the image, uploading it first if necessary.
+   // Requires a current OpenGL context.
+   pub(crate) fn texture_id(&self) -> GLuint {
+      if self.texture_id.get() == 0 {
+         self.texture_id.set(create_texture(self.width, self.height, self.pixels.as_ptr()));
+      }
+
+      self.texture_id.get()
+   }
+
+
+   // An image with an empty texture and no pixels in memory, for drawing into on the GPU.
+   // Requires a current OpenGL context.
+   pub(crate) fn gpu_only(width: u32, height: u32) -> Rc<Image> {
+      Rc::new(Image {
+         texture_id: Cell::new(create_texture(width, height, ptr::null())),
+         width: width,
+         height: height,
+         pixels: vec![],
+      })
+   }
+
+
+   // The color of the pixel at (x, y), which must be inside the image.
+   // Images that only exist on the GPU are transparent.
+   pub(crate) fn pixel(&self, x: u32, y: u32) -> (f32, f32, f32, f32) {
+      if self.pixels.len() == 0 {
+         return (0.0, 0.0, 0.0, 0.0);
+      }
+
+      let index = (y as usize * self.width as usize + x as usize) * 4;
+
+      (
+         self.pixels[index] as f32 / 255.0,
+         self.pixels[index + 1] as f32 / 255.0,
+         self.pixels[index + 2] as f32 / 255.0,
+         self.pixels[index + 3] as f32 / 255.0,
+      )
+   }
+}
+
+
+
+// Create a texture from RGBA pixels, top row first, or an uninitialized one if `pixels` is null
+fn create_texture(width: u32, height: u32, pixels: *const u8) -> GLuint {
+   unsafe {
+      let mut texture_id = 0;
+      gl::GenTextures(1, &mut texture_id);
+      gl::BindTexture(gl::TEXTURE_2D, texture_id);
+      gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+      gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+      gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+      gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+      gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA as i32, width as i32, height as i32, 0, gl::RGBA, gl::UNSIGNED_BYTE, pixels as *const _);
+      texture_id
+   }
+}
+
+
+
+// Save RGBA pixels, top row first, to a file. The format is picked from the file extension.
+// imagefmt can write PNG, TGA and BMP files. Anything else is an Unsupported error.
+pub(crate) fn write_rgba<P: AsRef<Path>>(path: P, width: u32, height: u32, pixels: &[u8]) -> Result<(), imagefmt::Error> {
+   // JPEG is common enough to deserve saying so plainly
+   let extension = path.as_ref().extension().and_then(|extension| extension.to_str()).map(|extension| extension.to_lowercase());
+   if extension.as_ref().map_or(false, |extension| extension == "jpg" || extension == "jpeg") {
+      return Err(imagefmt::Error::Unsupported("JPEG files can't be written, only PNG, TGA and BMP"));
+   }
+
+   imagefmt::write(path, width as usize, height as usize, imagefmt::ColFmt::RGBA, pixels, imagefmt::ColType::Auto)
 }
@@ -7,7 +7,24 @@ mod renderer;
 mod geometry;
 mod image;
 mod font;
+mod family;
+mod distance_field;
+mod stroke;
+mod gradient;
+mod pattern;
+mod software;
+mod target;
+mod text;
 
 pub use self::image::Image;
-pub use self::canvas::Canvas;
-pub use self::renderer::Renderer;
+pub use self::font::{Font, TextMetrics, TextRendering};
+pub use self::family::FontFamily;
+pub use self::text::{Paragraph, TextLayout, TextLine, TextAlign};
+pub use self::canvas::{Canvas, FillRule};
+pub use self::renderer::{Renderer, Antialiasing, RenderStats};
+pub use self::target::RenderTarget;
+pub use self::software::SoftwareRenderer;
+pub use self::geometry::{Point, Rect, Matrix, Decomposition};
+pub use self::stroke::{LineJoin, LineCap};
+pub use self::gradient::{Gradient, Spread};
+pub use self::pattern::{Pattern, Repetition};
@@ -0,0 +1,63 @@
+use std::rc::Rc;
+use super::geometry::Matrix;
+use super::Image;
+
+
+/// Which directions a pattern's image is tiled in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Repetition {
+   Repeat,
+   RepeatX,
+   RepeatY,
+   /// Draw the image once and leave everything around it transparent
+   NoRepeat,
+}
+
+
+/// An image tiled across a fill. Pass it to `Canvas::fill_pattern`.
+///
+/// The image's top left corner is at the origin of the coordinate system the
+/// figure is filled in, and one pixel of the image is one unit across.
+/// Give the pattern its own transform to move, scale or rotate the tiles.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+   image: Rc<Image>,
+   repetition: Repetition,
+   transform: Matrix,
+}
+
+
+impl Pattern {
+   pub fn new(image: Rc<Image>, repetition: Repetition) -> Pattern {
+      Pattern {
+         image: image,
+         repetition: repetition,
+         transform: Matrix::identity(),
+      }
+   }
+
+
+   /// Place the tiles with a transform, on top of the transform the figure is filled with
+   pub fn transform(mut self, transform: Matrix) -> Self {
+      self.transform = transform;
+      self
+   }
+}
+
+
+
+impl Pattern {
+   pub(crate) fn image(&self) -> &Rc<Image> {
+      &self.image
+   }
+
+
+   pub(crate) fn repetition(&self) -> Repetition {
+      self.repetition
+   }
+
+
+   pub(crate) fn pattern_transform(&self) -> Matrix {
+      self.transform
+   }
+}
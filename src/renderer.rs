@@ -2,11 +2,20 @@ use gl;
 use gl::types::*;
 
 use std::mem;
+use std::cell::Cell;
 use std::ptr;
 use std::str;
 use std::os::raw::c_void;
 use std::ffi::CString;
-use super::canvas::{Canvas, Fill};
+use super::canvas::{Canvas, Fill, FillRule, Figure};
+use super::font::{TextRun, TextRendering};
+use super::geometry::{Matrix, Point, Rect};
+use super::gradient::{Gradient, Spread};
+use super::pattern::Repetition;
+use super::{Image, RenderTarget};
+use super::image;
+use imagefmt;
+use std::path::Path;
 
 
 // Shader sources
@@ -15,18 +24,19 @@ static VS_SRC: &'static str = r#"
 
    // Uniforms
    uniform vec2 viewsize;
-   uniform vec2 point_a;
-   uniform vec2 point_b;
+   uniform float y_direction; // 1.0 when drawing to the window, -1.0 when drawing into a texture
+   uniform mat3 fill_transform;
 
    // Input
    in vec2 location;
+   in float coverage; // How much of the pixel the shape covers. Only less than 1.0 on anti-aliasing fringes.
+   in vec2 atlas_location; // Where the pixel is in the glyph atlas, for text
 
    // Output
    out VS_OUT {
-      vec2 location;
-      vec2 original_location;
-      vec2 point_a;
-      vec2 point_b;
+      vec2 fill_location;
+      float coverage;
+      vec2 atlas_location;
    } vs_out;
 
    // Prototypes
@@ -34,10 +44,11 @@ static VS_SRC: &'static str = r#"
 
 
    void main() {
-      vs_out.original_location = location;
-      vs_out.location = transform_point(location);
-      vs_out.point_a  = transform_point(point_a);
-      vs_out.point_b  = transform_point(point_b);
+      // Fill coordinates (gradient endpoints, image rectangles) are given in the
+      // coordinate system the figure was filled in, so map the point back into it
+      vs_out.fill_location = (fill_transform * vec3(location, 1.0)).xy;
+      vs_out.coverage = coverage;
+      vs_out.atlas_location = atlas_location;
 
       gl_Position = vec4(transform_point(location).xy, 0.0, 1.0);
    }
@@ -46,9 +57,10 @@ static VS_SRC: &'static str = r#"
    // Convert a point in pixel coordinates (from 0 to viewsize.xy)
    // to a point in OpenGL coordinates (from -1.0 to 1.0)
    vec2 transform_point(vec2 point) {
+      // The window's rows start at the bottom while a texture's start at the top
       return vec2(
           ((point.x / viewsize.x) * 2.0 - 1.0),
-         -((point.y / viewsize.y) * 2.0 - 1.0)
+         -((point.y / viewsize.y) * 2.0 - 1.0) * y_direction
       );
    }
 "#;
@@ -59,22 +71,35 @@ static FS_SRC: &'static str = r#"
    // Uniforms
    uniform int fill_type;
    uniform vec4 color_a;
-   uniform vec4 color_b;
+   uniform int stop_count;
+   uniform float stop_offsets[16];
+   uniform vec4 stop_colors[16];
+   uniform int spread;
    uniform vec2 point_a;
    uniform vec2 point_b;
+   uniform float radius_a;
+   uniform float radius_b;
+   uniform float angle_a;
    uniform sampler2D texture_a;
+   uniform mat3 texture_transform;
+   uniform int repetition;
+   uniform int glyph_atlas_type; // 0 for shapes, 1 for glyph coverage, 2 for signed distance fields
+   uniform sampler2D glyph_atlas;
 
    // Input
    in VS_OUT {
-      vec2 location;
-      vec2 original_location;
-      vec2 point_a;
-      vec2 point_b;
+      vec2 fill_location;
+      float coverage;
+      vec2 atlas_location;
    } fs_in;
 
    // Output
    out vec4 out_color;
 
+   // Prototypes
+   bool radial_gradient_position(vec2 location, out float position);
+   vec4 gradient_color(float position);
+
 
    void main() {
       // Solid Color
@@ -84,40 +109,201 @@ static FS_SRC: &'static str = r#"
 
       // Gradient
       else if (fill_type == 2) {
-         vec2 difference = fs_in.point_b - fs_in.point_a;
+         vec2 difference = point_b - point_a;
 
-         float multiplier = dot(fs_in.location - fs_in.point_a, normalize(difference)) / length(difference);
+         float multiplier = dot(fs_in.fill_location - point_a, difference) / dot(difference, difference);
 
-         out_color = vec4(
-            color_a.r + multiplier * (color_b.r - color_a.r),
-            color_a.g + multiplier * (color_b.g - color_a.g),
-            color_a.b + multiplier * (color_b.b - color_a.b),
-            color_a.a + multiplier * (color_b.a - color_a.a)
-         );
+         out_color = gradient_color(multiplier);
       }
 
       // Image
       else if (fill_type == 3) {
          vec2 texture_location = vec2(
-            (fs_in.original_location.x - point_a.x) / point_b.x,
-            (fs_in.original_location.y - point_a.y) / point_b.y
+            (fs_in.fill_location.x - point_a.x) / point_b.x,
+            (fs_in.fill_location.y - point_a.y) / point_b.y
          );
 
          out_color = texture(texture_a, texture_location);
       }
 
+      // Pattern
+      else if (fill_type == 6) {
+         vec2 texture_location = (texture_transform * vec3(fs_in.fill_location, 1.0)).xy / point_b;
+         bvec2 repeats = bvec2(repetition == 0 || repetition == 1, repetition == 0 || repetition == 2);
+
+         // Tiles only exist in the directions the pattern repeats in
+         if ((!repeats.x && (texture_location.x < 0.0 || texture_location.x >= 1.0)) ||
+             (!repeats.y && (texture_location.y < 0.0 || texture_location.y >= 1.0))) {
+            out_color = vec4(0.0);
+         } else {
+            out_color = texture(texture_a, fract(texture_location));
+         }
+      }
+
+      // Radial Gradient
+      else if (fill_type == 4) {
+         float multiplier;
+
+         // Outside the cone swept out between the two circles there's nothing to draw
+         if (radial_gradient_position(fs_in.fill_location, multiplier)) {
+            out_color = gradient_color(multiplier);
+         } else {
+            out_color = vec4(0.0);
+         }
+      }
+
+      // Conic Gradient
+      else if (fill_type == 5) {
+         vec2 offset = fs_in.fill_location - point_a;
+         float multiplier = fract((atan(offset.y, offset.x) - angle_a) / 6.28318530718);
+
+         out_color = gradient_color(multiplier);
+      }
+
       // This shouldn't happen. Output a truly awful green color for debugging purposes.
       else {
          out_color = vec4(0.3, 1.0, 0.0, 1.0);
       }
+
+      out_color.a *= fs_in.coverage;
+
+      // Text only covers as much of each pixel as the glyph rasterized into the atlas
+      if (glyph_atlas_type == 1) {
+         out_color.a *= texture(glyph_atlas, fs_in.atlas_location).r;
+      }
+
+      // Or, from a distance field, as much as is inside the outline, which is where the distance is over 0.5.
+      // The edge is smoothed over however much the distance changes across a pixel.
+      else if (glyph_atlas_type == 2) {
+         float distance = texture(glyph_atlas, fs_in.atlas_location).r;
+         float pixel = max(length(vec2(dFdx(distance), dFdy(distance))), 0.0001);
+         out_color.a *= clamp((distance - 0.5) / pixel + 0.5, 0.0, 1.0);
+      }
+   }
+
+
+   // Look up the color at `position` along the gradient described by the stop uniforms
+   vec4 gradient_color(float position) {
+      // Repeat
+      if (spread == 1) {
+         position = fract(position);
+      }
+
+      // Reflect
+      else if (spread == 2) {
+         position = 1.0 - abs(mod(position, 2.0) - 1.0);
+      }
+
+      // Pad
+      position = clamp(position, 0.0, 1.0);
+
+      if (stop_count == 0) {
+         return vec4(0.0);
+      }
+
+      if (position <= stop_offsets[0]) {
+         return stop_colors[0];
+      }
+
+      for (int i = 1; i < 16; i++) {
+         if (i >= stop_count) {
+            break;
+         }
+
+         if (position <= stop_offsets[i]) {
+            float span = stop_offsets[i] - stop_offsets[i - 1];
+            return mix(stop_colors[i - 1], stop_colors[i], span > 0.0 ? (position - stop_offsets[i - 1]) / span : 1.0);
+         }
+      }
+
+      return stop_colors[stop_count - 1];
+   }
+
+
+   // Find how far between the start circle (point_a, radius_a) and the end circle
+   // (point_b, radius_b) the circle passing through `location` is. Where several
+   // circles pass through the location, the one furthest along wins, as long as
+   // its radius isn't negative. Returns false if no circle passes through the location.
+   bool radial_gradient_position(vec2 location, out float position) {
+      vec2 center_difference = point_b - point_a;
+      vec2 location_difference = location - point_a;
+      float radius_difference = radius_b - radius_a;
+
+      float a = dot(center_difference, center_difference) - radius_difference * radius_difference;
+      float b = dot(location_difference, center_difference) + radius_a * radius_difference;
+      float c = dot(location_difference, location_difference) - radius_a * radius_a;
+
+      if (abs(a) < 0.0001) {
+         if (b == 0.0) { return false; }
+         position = c / (2.0 * b);
+         return radius_a + position * radius_difference >= 0.0;
+      }
+
+      float discriminant = b * b - a * c;
+      if (discriminant < 0.0) { return false; }
+
+      float root = sqrt(discriminant);
+      float larger = max((b + root) / a, (b - root) / a);
+      float smaller = min((b + root) / a, (b - root) / a);
+
+      position = larger;
+      if (radius_a + larger * radius_difference >= 0.0) { return true; }
+      position = smaller;
+      return radius_a + smaller * radius_difference >= 0.0;
    }
 "#;
 
 
+/// How a `Renderer` smooths the edges of shapes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Antialiasing {
+   /// Hard, jagged edges. Use this if the OpenGL context already does multisampling.
+   None,
+   /// Fade out a thin fringe just outside the edges of every shape
+   Fringe,
+}
+
+
+// How far out from a shape's edge, in pixels, the anti-aliasing fringe reaches
+const FRINGE_WIDTH: f32 = 0.5;
+
+// The most figures a batch can hold. Every figure added is checked for overlap with all the others.
+const MAX_BATCH_FIGURES: usize = 256;
+
+// Stencil bit marking pixels that have been drawn to by the current figure.
+// The winding number is counted in the bits below it.
+const DRAWN_BIT: GLuint = 0x80;
+
+
+/// Counts of the work done by a `Renderer`, for finding out where the time goes
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct RenderStats {
+   /// Figures drawn, one for each call to a fill or stroke method
+   pub figures: usize,
+   /// Groups of figures drawn together
+   pub batches: usize,
+   /// OpenGL draw calls made
+   pub draw_calls: usize,
+   /// Vertices sent through those draw calls
+   pub vertices: usize,
+}
+
+
 pub struct Renderer {
    vao: GLuint,
    vbo: GLuint,
+   vbo_capacity: Cell<usize>, // In bytes
+   fringe_vao: GLuint,
+   fringe_vbo: GLuint,
+   fringe_vbo_capacity: Cell<usize>, // In bytes
+   text_vao: GLuint,
+   text_vbo: GLuint,
+   text_vbo_capacity: Cell<usize>, // In bytes
    program: GLuint,
+   uniforms: Uniforms,
+   antialiasing: Antialiasing,
+   text_rendering: TextRendering,
+   stats: Cell<RenderStats>,
 }
 
 
@@ -129,6 +315,10 @@ impl Drop for Renderer {
          gl::DeleteProgram(self.program);
          gl::DeleteBuffers(1, &self.vbo);
          gl::DeleteVertexArrays(1, &self.vao);
+         gl::DeleteBuffers(1, &self.fringe_vbo);
+         gl::DeleteVertexArrays(1, &self.fringe_vao);
+         gl::DeleteBuffers(1, &self.text_vbo);
+         gl::DeleteVertexArrays(1, &self.text_vao);
       }
    }
 }
@@ -136,140 +326,731 @@ impl Drop for Renderer {
 
 
 impl Renderer {
+   /// Create a renderer. Edges are anti-aliased with `Antialiasing::Fringe` and text is drawn with
+   /// `TextRendering::Bitmap` unless told otherwise.
    pub fn new() -> Renderer {
       let program = link_program(VS_SRC, FS_SRC);
 
       let mut vao = 0;
       let mut vbo = 0;
+      let mut fringe_vao = 0;
+      let mut fringe_vbo = 0;
+      let mut text_vao = 0;
+      let mut text_vbo = 0;
 
       unsafe {
          // Create Vertex Array Object
          gl::GenVertexArrays(1, &mut vao);
          gl::BindVertexArray(vao);
 
-         // Create a Vertex Buffer Object and copy the vertex data to it
+         // Create a Vertex Buffer Object. It's given storage once there are points to put in it.
          gl::GenBuffers(1, &mut vbo);
          gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-         gl::BufferData(
-            gl::ARRAY_BUFFER,
-            (100 * mem::size_of::<(f32, f32)>()) as GLsizeiptr,
-            ptr::null(),
-            gl::STATIC_DRAW
-         );
 
          // Use shader program
          gl::UseProgram(program);
 
-         // Specify the layout of the vertex data
+         // Specify the layout of the vertex data. The coverage of the canvas's
+         // points is always 1.0, so it's given as a constant while drawing.
          gl::EnableVertexAttribArray(0);
          gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, ptr::null());
 
+         // Anti-aliasing fringes get their own buffer, with a coverage after each point
+         let stride = 3 * mem::size_of::<f32>() as GLsizei;
+         gl::GenVertexArrays(1, &mut fringe_vao);
+         gl::BindVertexArray(fringe_vao);
+         gl::GenBuffers(1, &mut fringe_vbo);
+         gl::BindBuffer(gl::ARRAY_BUFFER, fringe_vbo);
+         gl::EnableVertexAttribArray(0);
+         gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, ptr::null());
+         gl::EnableVertexAttribArray(1);
+         gl::VertexAttribPointer(1, 1, gl::FLOAT, gl::FALSE, stride, (2 * mem::size_of::<f32>()) as *const c_void);
+
+         // And so does text, with a location in the glyph atlas after each point
+         let stride = 4 * mem::size_of::<f32>() as GLsizei;
+         gl::GenVertexArrays(1, &mut text_vao);
+         gl::BindVertexArray(text_vao);
+         gl::GenBuffers(1, &mut text_vbo);
+         gl::BindBuffer(gl::ARRAY_BUFFER, text_vbo);
+         gl::EnableVertexAttribArray(0);
+         gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, ptr::null());
+         gl::EnableVertexAttribArray(2);
+         gl::VertexAttribPointer(2, 2, gl::FLOAT, gl::FALSE, stride, (2 * mem::size_of::<f32>()) as *const c_void);
+
          gl::BindVertexArray(0);
 
          Renderer {
             vao: vao,
             vbo: vbo,
+            vbo_capacity: Cell::new(0),
+            fringe_vao: fringe_vao,
+            fringe_vbo: fringe_vbo,
+            fringe_vbo_capacity: Cell::new(0),
+            text_vao: text_vao,
+            text_vbo: text_vbo,
+            text_vbo_capacity: Cell::new(0),
             program: program,
+            uniforms: Uniforms::new(program),
+            antialiasing: Antialiasing::Fringe,
+            text_rendering: TextRendering::Bitmap,
+            stats: Cell::new(RenderStats::default()),
          }
       }
    }
 
 
 
+   /// Choose how the edges of shapes are smoothed
+   pub fn set_antialiasing(&mut self, antialiasing: Antialiasing) {
+      self.antialiasing = antialiasing;
+   }
+
+
+   pub fn antialiasing(&self) -> Antialiasing {
+      self.antialiasing
+   }
+
+
+   /// Choose how text is drawn
+   pub fn set_text_rendering(&mut self, text_rendering: TextRendering) {
+      self.text_rendering = text_rendering;
+   }
+
+
+   pub fn text_rendering(&self) -> TextRendering {
+      self.text_rendering
+   }
+
+
+
    pub fn draw_canvas(&self, window_width: u16, window_height: u16, canvas: &Canvas) {
+      self.draw(window_width as f32, window_height as f32, 1.0, canvas);
+   }
+
+
+   /// Draw a canvas into a render target instead of the window.
+   /// Don't fill shapes with the target's own image while drawing into it.
+   pub fn draw_canvas_to(&self, target: &RenderTarget, canvas: &Canvas) {
+      // Images are stored top row first, so the canvas is drawn upside down compared
+      // to the window. That way the target's image can be used like any other.
+      target.with_bound(|| self.draw(target.width() as f32, target.height() as f32, -1.0, canvas));
+   }
+
+
+   fn draw(&self, view_width: f32, view_height: f32, y_direction: f32, canvas: &Canvas) {
       // Drawing a figure takes two steps. First, the figure is drawn to the
       // stencil buffer. Second, the figure is drawn to the color buffer. This
       // invokes some neat geometric sorcery that allows drawing concave
       // polygons without first triangulating them for OpenGL.
       // See the webpage below for more information on the technique.
       // http://what-when-how.com/opengl-programming-guide/drawing-filled-concave-polygons-using-the-stencil-buffer-opengl-programming/
-      unsafe {
-         let (points_buffer, points_buffer_length) = canvas.get_points_buffer();
+      let (points_buffer, points_buffer_length) = canvas.get_points_buffer();
 
+      // Nothing to draw
+      if points_buffer_length == 0 && canvas.figures_iter().all(|figure| figure.text.len() == 0) {
+         return;
+      }
+
+      unsafe {
          // Activate the buffer that stores the canvas's points
          gl::BindVertexArray(self.vao);
+         gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
 
          // Activate the vector drawing program
          gl::UseProgram(self.program);
 
          // Upload the canvas's points to the GPU
-         gl::BufferSubData(gl::ARRAY_BUFFER, 0, points_buffer_length as isize, points_buffer as *const c_void);
+         if points_buffer_length > 0 {
+            upload_vertices(&self.vbo_capacity, points_buffer as *const c_void, points_buffer_length);
+         }
+         gl::VertexAttrib1f(1, 1.0);
+
+         // And the fringes around them
+         let (fringe_vertices, fringe_ranges) = match self.antialiasing {
+            Antialiasing::None => (vec![], vec![]),
+            Antialiasing::Fringe => fringes(canvas),
+         };
+
+         if fringe_vertices.len() > 0 {
+            gl::BindVertexArray(self.fringe_vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.fringe_vbo);
+            upload_vertices(&self.fringe_vbo_capacity, fringe_vertices.as_ptr() as *const c_void, fringe_vertices.len() * mem::size_of::<f32>());
+            gl::BindVertexArray(self.vao);
+         }
 
          // Tell the GPU how big the window is so that it can convert pixel coordinates into OpenGL coordinates
-         gl::Uniform2f(self.get_uniform_location("viewsize"), window_width as f32, window_height as f32);
+         gl::Uniform2f(self.uniforms.viewsize, view_width, view_height);
+         gl::Uniform1f(self.uniforms.y_direction, y_direction);
+         gl::Uniform1i(self.uniforms.glyph_atlas_type, 0);
 
          // Invoke the sorcery of Geometry!
          gl::Enable(gl::STENCIL_TEST);
 
-         // Draw each figure in the canvas
-         for figure in canvas.figures_iter() {
-            // Tell the GPU what type of fill to use
-            match figure.fill {
-               Fill::SolidColor((red, green, blue, alpha)) => {
-                  let fill_type = self.get_uniform_location("fill_type");
-                  let color_a = self.get_uniform_location("color_a");
-                  gl::Uniform1i(fill_type, 1);
-                  gl::Uniform4f(color_a, red, green, blue, alpha);
-               },
-
-               Fill::LinearGradient(begin, end, begin_color, end_color) => {
-                  let fill_type = self.get_uniform_location("fill_type");
-                  let color_a = self.get_uniform_location("color_a");
-                  let color_b = self.get_uniform_location("color_b");
-                  let point_a = self.get_uniform_location("point_a");
-                  let point_b = self.get_uniform_location("point_b");
-                  gl::Uniform1i(fill_type, 2);
-                  gl::Uniform4f(color_a, begin_color.0, begin_color.1, begin_color.2, begin_color.3);
-                  gl::Uniform4f(color_b, end_color.0, end_color.1, end_color.2, end_color.3);
-                  gl::Uniform2f(point_a, begin.x, begin.y);
-                  gl::Uniform2f(point_b, end.x, end.y);
-               },
-
-               Fill::Image(ref image, location, width, height) => {
-                  let fill_type = self.get_uniform_location("fill_type");
-                  let image_a = self.get_uniform_location("image_a");
-                  let point_a = self.get_uniform_location("point_a");
-                  let point_b = self.get_uniform_location("point_b");
-                  gl::Uniform1i(fill_type, 3);
-                  gl::Uniform1i(image_a, image.texture_id as i32);
-                  gl::Uniform2f(point_a, location.x, location.y);
-                  gl::Uniform2f(point_b, width, height);
-               },
+         // Draw over what's already there the same way the software renderer does
+         gl::Enable(gl::BLEND);
+         gl::BlendFuncSeparate(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA, gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+
+         let batches = batches(canvas, &fringe_ranges);
+         let mut stats = self.stats.get();
+         stats.figures += canvas.figures_iter().count();
+         stats.batches += batches.len();
+         self.stats.set(stats);
+
+         // Draw each batch of figures in the canvas
+         for (batch_index, batch) in batches.iter().enumerate() {
+            let figure = batch.figure;
+
+            // Neighboring batches often share a fill. Only upload it when it changes.
+            let previous = if batch_index > 0 { Some(batches[batch_index - 1].figure) } else { None };
+            if !previous.map_or(false, |previous| previous.transform == figure.transform && previous.fill.same_as(&figure.fill)) {
+               self.set_fill(figure);
             }
 
-            // Draw each path in the figure to the buffer
-            for &(path_index, path_length) in &figure.paths {
-               // First draw to the stencil buffer so that concave shapes appear correctly.
-               // It's possible to optimize this call away for convex polygons. Someone should do this at some point.
-               gl::StencilMask(0xff);
-               gl::StencilFunc(gl::ALWAYS, 0, 0xff);
-               gl::StencilOp(gl::INVERT, gl::INVERT, gl::INVERT);
+            if figure.text.len() > 0 {
+               self.draw_text(&figure.text);
+            }
+
+            if batch.counts.len() == 0 {
+               continue;
+            }
+
+            gl::StencilMask(0xff);
+
+            if batch.convex {
+               // The triangles of a convex figure never overlap, so it can be drawn straight to the color buffer
+               gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+
+               if batch.fringe.1 == 0 {
+                  gl::StencilFunc(gl::ALWAYS, 0, 0xff);
+                  gl::StencilOp(gl::KEEP, gl::KEEP, gl::KEEP);
+                  self.draw_paths(batch);
+                  continue;
+               }
+
+               // Mark the inside of the figure as drawn, for the fringes
+               gl::StencilFunc(gl::ALWAYS, DRAWN_BIT as i32, 0xff);
+               gl::StencilOp(gl::KEEP, gl::KEEP, gl::REPLACE);
+               self.draw_paths(batch);
+            } else {
+               // First draw every path in the batch to the stencil buffer so that concave shapes and holes appear correctly
                gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
-               gl::DrawArrays(gl::TRIANGLE_FAN, path_index as i32, path_length as i32);
+               gl::StencilFunc(gl::ALWAYS, 0, 0xff);
 
-               // Draw to the color buffer
+               let stencil_mask = match figure.fill_rule {
+                  // Count how many times each pixel is wound around. Clockwise and
+                  // anticlockwise triangles count in opposite directions.
+                  FillRule::NonZero => {
+                     gl::StencilMask(!DRAWN_BIT & 0xff);
+                     gl::StencilOpSeparate(gl::FRONT, gl::KEEP, gl::KEEP, gl::INCR_WRAP);
+                     gl::StencilOpSeparate(gl::BACK, gl::KEEP, gl::KEEP, gl::DECR_WRAP);
+                     !DRAWN_BIT & 0xff
+                  },
+
+                  // Only track whether each pixel has been covered an odd number of times
+                  FillRule::EvenOdd => {
+                     gl::StencilMask(0x01);
+                     gl::StencilOp(gl::KEEP, gl::KEEP, gl::INVERT);
+                     0x01
+                  },
+               };
+
+               self.draw_paths(batch);
+
+               // Draw to the color buffer wherever the stencil says the figure is, so each pixel is only drawn once
                gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
-               gl::StencilFunc(gl::EQUAL, 0xff, 0xff);
-               gl::StencilOp(gl::KEEP, gl::KEEP, gl::KEEP);
-               gl::DrawArrays(gl::TRIANGLE_FAN, path_index as i32, path_length as i32);
+               gl::StencilMask(0xff);
+
+               if batch.fringe.1 == 0 {
+                  // Clear the stencil as it goes so the next batch starts fresh
+                  gl::StencilFunc(gl::NOTEQUAL, 0, stencil_mask);
+                  gl::StencilOp(gl::ZERO, gl::ZERO, gl::ZERO);
+                  self.draw_paths(batch);
+                  continue;
+               }
+
+               // Mark the inside of the figure as drawn. The winding number doesn't matter anymore.
+               gl::StencilFunc(gl::NOTEQUAL, DRAWN_BIT as i32, stencil_mask);
+               gl::StencilOp(gl::KEEP, gl::KEEP, gl::REPLACE);
+               self.draw_paths(batch);
             }
+
+            // Then fade out the edges, only outside the figure and only once where fringes overlap
+            gl::BindVertexArray(self.fringe_vao);
+            gl::StencilFunc(gl::NOTEQUAL, DRAWN_BIT as i32, DRAWN_BIT);
+            self.draw_fringe(batch.fringe);
+
+            // Finally clear everything that was marked so the next batch starts fresh
+            gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+            gl::StencilFunc(gl::ALWAYS, 0, 0xff);
+            gl::StencilOp(gl::ZERO, gl::ZERO, gl::ZERO);
+            self.draw_fringe(batch.fringe);
+            gl::BindVertexArray(self.vao);
+            self.draw_paths(batch);
+
+            gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
          }
 
+         gl::Disable(gl::BLEND);
          gl::Disable(gl::STENCIL_TEST);
       }
    }
 
 
-   fn get_uniform_location(&self, name_str: &str) -> GLint {
+   // Upload everything the fill shader needs to know about how a figure is filled
+   unsafe fn set_fill(&self, figure: &Figure) {
+      // Tell the GPU how to get from canvas coordinates back to the coordinates the fill was given in
+      let fill_transform = figure.transform.inverse().unwrap_or(Matrix::identity());
+      gl::UniformMatrix3fv(self.uniforms.fill_transform, 1, gl::TRUE, fill_transform.as_array().as_ptr());
+
+      // Tell the GPU what type of fill to use
+      match figure.fill {
+         Fill::SolidColor((red, green, blue, alpha)) => {
+            gl::Uniform1i(self.uniforms.fill_type, 1);
+            gl::Uniform4f(self.uniforms.color_a, red, green, blue, alpha);
+         },
+
+         Fill::LinearGradient(begin, end, ref gradient) => {
+            gl::Uniform1i(self.uniforms.fill_type, 2);
+            gl::Uniform2f(self.uniforms.point_a, begin.x, begin.y);
+            gl::Uniform2f(self.uniforms.point_b, end.x, end.y);
+            self.set_gradient(gradient);
+         },
+
+         Fill::RadialGradient(begin, begin_radius, end, end_radius, ref gradient) => {
+            gl::Uniform1i(self.uniforms.fill_type, 4);
+            gl::Uniform2f(self.uniforms.point_a, begin.x, begin.y);
+            gl::Uniform2f(self.uniforms.point_b, end.x, end.y);
+            gl::Uniform1f(self.uniforms.radius_a, begin_radius);
+            gl::Uniform1f(self.uniforms.radius_b, end_radius);
+            self.set_gradient(gradient);
+         },
+
+         Fill::ConicGradient(center, start_angle, ref gradient) => {
+            gl::Uniform1i(self.uniforms.fill_type, 5);
+            gl::Uniform2f(self.uniforms.point_a, center.x, center.y);
+            gl::Uniform1f(self.uniforms.angle_a, start_angle);
+            self.set_gradient(gradient);
+         },
+
+         Fill::Image(ref image, location, width, height) => {
+            gl::Uniform1i(self.uniforms.fill_type, 3);
+            self.set_texture(image);
+            gl::Uniform2f(self.uniforms.point_a, location.x, location.y);
+            gl::Uniform2f(self.uniforms.point_b, width, height);
+         },
+
+         Fill::Pattern(ref pattern) => {
+            let pattern_inverse = pattern.pattern_transform().inverse().unwrap_or(Matrix::identity());
+            let repetition_mode = match pattern.repetition() {
+               Repetition::Repeat => 0,
+               Repetition::RepeatX => 1,
+               Repetition::RepeatY => 2,
+               Repetition::NoRepeat => 3,
+            };
+            gl::Uniform1i(self.uniforms.fill_type, 6);
+            self.set_texture(pattern.image());
+            gl::Uniform2f(self.uniforms.point_b, pattern.image().width() as f32, pattern.image().height() as f32);
+            gl::UniformMatrix3fv(self.uniforms.texture_transform, 1, gl::TRUE, pattern_inverse.as_array().as_ptr());
+            gl::Uniform1i(self.uniforms.repetition, repetition_mode);
+         },
+      }
+   }
+
+
+   /// How much work the renderer has done since it was created or `reset_stats` was last called
+   pub fn stats(&self) -> RenderStats {
+      self.stats.get()
+   }
+
+
+   pub fn reset_stats(&self) {
+      self.stats.set(RenderStats::default());
+   }
+
+
+   /// Read back what has been drawn to the bound framebuffer as RGBA pixels,
+   /// 8 bits per channel, starting with the top row.
+   /// Works for the window as well as offscreen framebuffers.
+   pub fn read_pixels(&self, width: u32, height: u32) -> Vec<u8> {
+      let row_length = width as usize * 4;
+      let mut pixels = vec![0u8; row_length * height as usize];
+
       unsafe {
-         let name = CString::new(name_str.as_bytes()).unwrap();
-         gl::GetUniformLocation(self.program, name.as_ptr())
+         gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+         gl::ReadPixels(0, 0, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_mut_ptr() as *mut c_void);
+      }
+
+      // OpenGL starts with the bottom row
+      let mut flipped = Vec::with_capacity(pixels.len());
+      for row in pixels.chunks(row_length.max(1)).rev() {
+         flipped.extend_from_slice(row);
+      }
+
+      flipped
+   }
+
+
+   /// Save what has been drawn to the bound framebuffer to a PNG, TGA or BMP file, picked by the file's extension.
+   /// JPEG isn't supported: a .jpg or .jpeg path gives an `Unsupported` error.
+   pub fn save<P: AsRef<Path>>(&self, path: P, width: u32, height: u32) -> Result<(), imagefmt::Error> {
+      image::write_rgba(path, width, height, &self.read_pixels(width, height))
+   }
+
+
+   // Bind an image to the texture unit the fill shader samples from
+   unsafe fn set_texture(&self, image: &Image) {
+      gl::ActiveTexture(gl::TEXTURE0);
+      gl::BindTexture(gl::TEXTURE_2D, image.texture_id());
+      gl::Uniform1i(self.uniforms.texture_a, 0);
+   }
+
+
+   // Upload a gradient's stops and spread mode
+   unsafe fn set_gradient(&self, gradient: &Gradient) {
+      let offsets: Vec<f32> = gradient.stops().iter().map(|&(offset, _)| offset).collect();
+      let colors: Vec<f32> = gradient.stops().iter().flat_map(|&(_, color)| vec![color.0, color.1, color.2, color.3]).collect();
+      let spread = match gradient.spread_mode() {
+         Spread::Pad => 0,
+         Spread::Repeat => 1,
+         Spread::Reflect => 2,
+      };
+
+      gl::Uniform1i(self.uniforms.stop_count, offsets.len() as i32);
+      gl::Uniform1i(self.uniforms.spread, spread);
+
+      if offsets.len() > 0 {
+         gl::Uniform1fv(self.uniforms.stop_offsets, offsets.len() as i32, offsets.as_ptr());
+         gl::Uniform4fv(self.uniforms.stop_colors, offsets.len() as i32, colors.as_ptr());
+      }
+   }
+
+
+   // Draw the glyphs of some text as quads, with their coverage taken from the glyph atlas
+   // or the distance field atlas. Neighbouring quads overlap, distance field ones always since
+   // they reach FIELD_SPREAD atlas pixels past the outline, but outside a glyph's outline its
+   // coverage is 0 and blending leaves the pixel alone. So the stencil buffer isn't needed.
+   unsafe fn draw_text(&self, runs: &[TextRun]) {
+      let atlas_type = match self.text_rendering {
+         TextRendering::Bitmap => 1,
+         TextRendering::DistanceField => 2,
+      };
+
+      gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+      gl::StencilFunc(gl::ALWAYS, 0, 0xff);
+      gl::StencilOp(gl::KEEP, gl::KEEP, gl::KEEP);
+      gl::Uniform1i(self.uniforms.glyph_atlas_type, atlas_type);
+      gl::Uniform1i(self.uniforms.glyph_atlas, 1);
+      gl::BindVertexArray(self.text_vao);
+      gl::BindBuffer(gl::ARRAY_BUFFER, self.text_vbo);
+
+      // Glyph atlases go in their own texture unit so they don't disturb the fill's image
+      gl::ActiveTexture(gl::TEXTURE1);
+
+      for run in runs {
+         let (atlas, quads) = glyph_quads(run, self.text_rendering);
+         let mut vertices: Vec<f32> = vec![];
+
+         for &(pixels, texture) in &quads {
+            let corner = |x: f32, y: f32, u: f32, v: f32| {
+               let point = run.transform * Point::new(x, y);
+               [point.x, point.y, u, v]
+            };
+            let top_left = corner(pixels.x, pixels.y, texture.x, texture.y);
+            let top_right = corner(pixels.x + pixels.width, pixels.y, texture.x + texture.width, texture.y);
+            let bottom_right = corner(pixels.x + pixels.width, pixels.y + pixels.height, texture.x + texture.width, texture.y + texture.height);
+            let bottom_left = corner(pixels.x, pixels.y + pixels.height, texture.x, texture.y + texture.height);
+
+            for vertex in &[top_left, top_right, bottom_right, top_left, bottom_right, bottom_left] {
+               vertices.extend_from_slice(vertex);
+            }
+         }
+
+         if vertices.len() == 0 {
+            continue;
+         }
+
+         upload_vertices(&self.text_vbo_capacity, vertices.as_ptr() as *const c_void, vertices.len() * mem::size_of::<f32>());
+         gl::BindTexture(gl::TEXTURE_2D, atlas);
+
+         let count = (vertices.len() / 4) as GLsizei;
+         gl::DrawArrays(gl::TRIANGLES, 0, count);
+
+         let mut stats = self.stats.get();
+         stats.draw_calls += 1;
+         stats.vertices += count as usize;
+         self.stats.set(stats);
+      }
+
+      gl::ActiveTexture(gl::TEXTURE0);
+      gl::Uniform1i(self.uniforms.glyph_atlas_type, 0);
+      gl::BindVertexArray(self.vao);
+      gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+   }
+
+
+   // Draw every path in a batch as triangle fans, in a single call
+   unsafe fn draw_paths(&self, batch: &Batch) {
+      gl::MultiDrawArrays(gl::TRIANGLE_FAN, batch.firsts.as_ptr(), batch.counts.as_ptr(), batch.counts.len() as GLsizei);
+
+      let mut stats = self.stats.get();
+      stats.draw_calls += 1;
+      stats.vertices += batch.counts.iter().sum::<GLsizei>() as usize;
+      self.stats.set(stats);
+   }
+
+
+   // Draw (first vertex, vertex count) of the fringe buffer as triangles. The fringe vertex array must be bound.
+   unsafe fn draw_fringe(&self, (first, count): (GLint, GLsizei)) {
+      gl::DrawArrays(gl::TRIANGLES, first, count);
+
+      let mut stats = self.stats.get();
+      stats.draw_calls += 1;
+      stats.vertices += count as usize;
+      self.stats.set(stats);
+   }
+}
+
+
+
+// Where each uniform in the shader program lives, looked up once after linking
+struct Uniforms {
+   viewsize: GLint,
+   y_direction: GLint,
+   fill_transform: GLint,
+   fill_type: GLint,
+   color_a: GLint,
+   stop_count: GLint,
+   stop_offsets: GLint,
+   stop_colors: GLint,
+   spread: GLint,
+   point_a: GLint,
+   point_b: GLint,
+   radius_a: GLint,
+   radius_b: GLint,
+   angle_a: GLint,
+   texture_a: GLint,
+   texture_transform: GLint,
+   repetition: GLint,
+   glyph_atlas_type: GLint,
+   glyph_atlas: GLint,
+}
+
+
+impl Uniforms {
+   fn new(program: GLuint) -> Uniforms {
+      let location = |name: &str| unsafe {
+         let name = CString::new(name.as_bytes()).unwrap();
+         gl::GetUniformLocation(program, name.as_ptr())
+      };
+
+      Uniforms {
+         viewsize: location("viewsize"),
+         y_direction: location("y_direction"),
+         fill_transform: location("fill_transform"),
+         fill_type: location("fill_type"),
+         color_a: location("color_a"),
+         stop_count: location("stop_count"),
+         stop_offsets: location("stop_offsets"),
+         stop_colors: location("stop_colors"),
+         spread: location("spread"),
+         point_a: location("point_a"),
+         point_b: location("point_b"),
+         radius_a: location("radius_a"),
+         radius_b: location("radius_b"),
+         angle_a: location("angle_a"),
+         texture_a: location("texture_a"),
+         texture_transform: location("texture_transform"),
+         repetition: location("repetition"),
+         glyph_atlas_type: location("glyph_atlas_type"),
+         glyph_atlas: location("glyph_atlas"),
       }
    }
 }
 
 
 
+// The quad around each glyph of a run that has a shape, in the coordinates the run was laid out in,
+// and where it is in the atlas texture, in texture coordinates. Along with that texture.
+// Glyphs are added to the atlas as needed.
+fn glyph_quads(run: &TextRun, text_rendering: TextRendering) -> (GLuint, Vec<(Rect, Rect)>) {
+   match text_rendering {
+      TextRendering::Bitmap => {
+         let atlas = run.font.cache_glyphs(&run.glyphs);
+         let quads = run.glyphs.iter()
+            .filter_map(|glyph| run.font.atlas_rect(glyph))
+            .map(|(texture, pixels)| (pixels, texture))
+            .collect();
+
+         (atlas, quads)
+      }
+
+      TextRendering::DistanceField => {
+         let fields: Vec<(Rect, Rect)> = run.glyphs.iter()
+            .filter_map(|glyph| run.font.distance_field(glyph).map(|field| (field.placed(glyph), field.atlas)))
+            .collect();
+
+         // The atlas can grow while fields are added, so texture coordinates wait until they're all in
+         let (atlas, size) = run.font.upload_distance_fields();
+         let size = size as f32;
+         let quads = fields.into_iter().map(|(pixels, field)| {
+            (pixels, Rect::new(field.x / size, field.y / size, field.width / size, field.height / size))
+         }).collect();
+
+         (atlas, quads)
+      }
+   }
+}
+
+
+
+// Consecutive figures that are filled the same way and don't overlap, drawn together.
+// Keeping them apart would only matter where they overlap.
+struct Batch<'a> {
+   figure: &'a Figure, // The first figure, which has the fill, transform and fill rule for all of them
+   firsts: Vec<GLint>, // Where each path starts in the point buffer
+   counts: Vec<GLsizei>, // How many points are in each path
+   fringe: (GLint, GLsizei), // The first vertex and vertex count of the fringes around every figure
+   convex: bool, // Whether every figure is a single convex path
+   bounds: Vec<Rect>, // Of each figure, grown to include anything the fringes might touch
+}
+
+
+
+// Group the canvas's figures into batches. `fringe_ranges` has the (first vertex, vertex count)
+// of each figure's fringe, or is empty if there are none.
+fn batches<'a>(canvas: &'a Canvas, fringe_ranges: &[(GLint, GLsizei)]) -> Vec<Batch<'a>> {
+   let points = canvas.points();
+   let mut batches: Vec<Batch> = vec![];
+
+   for (figure_index, figure) in canvas.figures_iter().enumerate() {
+      let fringe = fringe_ranges.get(figure_index).cloned().unwrap_or((0, 0));
+      let bounds = figure_bounds(points, figure, FRINGE_WIDTH + 1.0);
+      let convex = figure.paths.len() == 1 && figure.paths[0].convex;
+
+      let joins_previous = batches.last().map_or(false, |batch| {
+         batch.bounds.len() < MAX_BATCH_FIGURES &&
+         batch.figure.text.len() == 0 && figure.text.len() == 0 &&
+         batch.convex == convex &&
+         batch.figure.fill_rule == figure.fill_rule &&
+         batch.figure.transform == figure.transform &&
+         batch.figure.fill.same_as(&figure.fill) &&
+         batch.fringe.0 + batch.fringe.1 == fringe.0 &&
+         batch.bounds.iter().all(|other| !overlaps(other, &bounds))
+      });
+
+      if !joins_previous {
+         batches.push(Batch {
+            figure: figure,
+            firsts: vec![],
+            counts: vec![],
+            fringe: (fringe.0, 0),
+            convex: convex,
+            bounds: vec![],
+         });
+      }
+
+      let batch = batches.last_mut().unwrap();
+      batch.fringe.1 += fringe.1;
+      batch.bounds.push(bounds);
+
+      for path in &figure.paths {
+         batch.firsts.push(path.index as GLint);
+         batch.counts.push(path.length as GLsizei);
+      }
+   }
+
+   batches
+}
+
+
+
+// The smallest rectangle containing every point of a figure, grown by `margin` on each side
+fn figure_bounds(points: &[Point], figure: &Figure, margin: f32) -> Rect {
+   let mut min = Point::new(::std::f32::INFINITY, ::std::f32::INFINITY);
+   let mut max = Point::new(::std::f32::NEG_INFINITY, ::std::f32::NEG_INFINITY);
+
+   for path in &figure.paths {
+      for point in &points[path.index..path.index + path.length] {
+         min = Point::new(min.x.min(point.x), min.y.min(point.y));
+         max = Point::new(max.x.max(point.x), max.y.max(point.y));
+      }
+   }
+
+   Rect::new(min.x - margin, min.y - margin, max.x - min.x + 2.0 * margin, max.y - min.y + 2.0 * margin)
+}
+
+
+
+fn overlaps(a: &Rect, b: &Rect) -> bool {
+   a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+}
+
+
+
+
+
+
+// Copy `length` bytes of vertices into the bound vertex buffer, whose size is kept in `capacity`.
+// The buffer doubles in size whenever it's too small. Otherwise its old storage is orphaned before
+// writing, so the driver doesn't have to wait for draws still using the last canvas.
+unsafe fn upload_vertices(capacity: &Cell<usize>, vertices: *const c_void, length: usize) {
+   if length > capacity.get() {
+      capacity.set(length.next_power_of_two());
+   }
+
+   gl::BufferData(gl::ARRAY_BUFFER, capacity.get() as GLsizeiptr, ptr::null(), gl::STREAM_DRAW);
+   gl::BufferSubData(gl::ARRAY_BUFFER, 0, length as GLsizeiptr, vertices);
+}
+
+
+
+
+// Build the anti-aliasing fringe of every figure: a thin band along both sides of each edge,
+// fully covered halfway at the edge and fading out towards the sides. The half inside the
+// figure is never drawn. Returns (x, y, coverage) vertices for drawing as triangles,
+// and the first vertex and vertex count of each figure.
+fn fringes(canvas: &Canvas) -> (Vec<f32>, Vec<(i32, i32)>) {
+   let points = canvas.points();
+   let mut vertices = vec![];
+   let mut ranges = vec![];
+
+   for figure in canvas.figures_iter() {
+      let first = vertices.len() / 3;
+
+      for path in &figure.paths {
+         let path_points = &points[path.index..path.index + path.length];
+
+         for (i, &start) in path_points.iter().enumerate() {
+            let end = path_points[(i + 1) % path_points.len()];
+            let (dx, dy) = (end.x - start.x, end.y - start.y);
+            let length = (dx * dx + dy * dy).sqrt();
+
+            if !(length > 0.0) {
+               continue;
+            }
+
+            let normal = (-dy / length * FRINGE_WIDTH, dx / length * FRINGE_WIDTH);
+
+            for &side in &[1.0, -1.0] {
+               let (nx, ny) = (normal.0 * side, normal.1 * side);
+               let outer_start = [start.x + nx, start.y + ny, 0.0];
+               let outer_end = [end.x + nx, end.y + ny, 0.0];
+               let inner_start = [start.x, start.y, 0.5];
+               let inner_end = [end.x, end.y, 0.5];
+
+               for vertex in &[inner_start, inner_end, outer_end, inner_start, outer_end, outer_start] {
+                  vertices.extend_from_slice(vertex);
+               }
+            }
+         }
+      }
+
+      ranges.push((first as i32, (vertices.len() / 3 - first) as i32));
+   }
+
+   (vertices, ranges)
+}
+
 
 
 
@@ -317,6 +1098,15 @@ fn link_program(vs: &str, fs: &str) -> GLuint {
       let fragment_shader = compile_shader(fs, gl::FRAGMENT_SHADER);
       gl::AttachShader(program, vertex_shader);
       gl::AttachShader(program, fragment_shader);
+
+      // Pin down the attribute locations the vertex array objects are set up with
+      let location = CString::new("location").unwrap();
+      let coverage = CString::new("coverage").unwrap();
+      let atlas_location = CString::new("atlas_location").unwrap();
+      gl::BindAttribLocation(program, 0, location.as_ptr());
+      gl::BindAttribLocation(program, 1, coverage.as_ptr());
+      gl::BindAttribLocation(program, 2, atlas_location.as_ptr());
+
       gl::LinkProgram(program);
 
       // Cleanup the shaders used
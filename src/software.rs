@@ -0,0 +1,533 @@
+use std::f32::consts;
+use super::canvas::{Canvas, Figure, Fill, FillRule};
+use super::font::{TextRun, TextRendering};
+use super::distance_field::FIELD_SPREAD;
+use super::geometry::{Matrix, Point, Rect};
+use super::pattern::Repetition;
+use super::Image;
+use super::image;
+use imagefmt;
+use std::path::Path;
+
+
+// Each row of pixels is sampled this many times from top to bottom.
+// Coverage across the row is measured exactly, so this only limits the
+// quality of edges that are close to horizontal.
+const SAMPLES_PER_ROW: usize = 16;
+
+type Color = (f32, f32, f32, f32);
+
+
+/// Draws canvases into RGBA pixels in memory, without needing OpenGL.
+///
+/// Figures are drawn the same way as with a `Renderer`, with the same fills and
+/// fill rules, and are blended on top of what's already there. Edges are anti-aliased.
+pub struct SoftwareRenderer {
+   width: u32,
+   height: u32,
+   pixels: Vec<u8>, // RGBA, 8 bits per channel, top row first
+   text_rendering: TextRendering,
+}
+
+
+
+impl SoftwareRenderer {
+   /// Create a renderer with every pixel transparent
+   pub fn new(width: u32, height: u32) -> SoftwareRenderer {
+      SoftwareRenderer {
+         width: width,
+         height: height,
+         pixels: vec![0; width as usize * height as usize * 4],
+         text_rendering: TextRendering::Bitmap,
+      }
+   }
+
+
+   /// Choose how text is drawn, to match a `Renderer`. Defaults to `TextRendering::Bitmap`.
+   pub fn set_text_rendering(&mut self, text_rendering: TextRendering) {
+      self.text_rendering = text_rendering;
+   }
+
+
+   pub fn text_rendering(&self) -> TextRendering {
+      self.text_rendering
+   }
+
+
+   /// Set every pixel to a color
+   pub fn clear(&mut self, red: f32, green: f32, blue: f32, alpha: f32) {
+      let color = [to_byte(red), to_byte(green), to_byte(blue), to_byte(alpha)];
+
+      for pixel in self.pixels.chunks_mut(4) {
+         pixel.copy_from_slice(&color);
+      }
+   }
+
+
+   pub fn draw_canvas(&mut self, canvas: &Canvas) {
+      for figure in canvas.figures_iter() {
+         for run in &figure.text {
+            self.draw_text(run, figure);
+         }
+
+         self.draw_figure(canvas.points(), figure);
+      }
+   }
+
+
+   pub fn width(&self) -> u32 {
+      self.width
+   }
+
+
+   pub fn height(&self) -> u32 {
+      self.height
+   }
+
+
+   /// The rendered pixels: RGBA, 8 bits per channel, starting with the top row
+   pub fn pixels(&self) -> &[u8] {
+      &self.pixels
+   }
+
+
+   pub fn into_pixels(self) -> Vec<u8> {
+      self.pixels
+   }
+
+
+   /// Save the rendered pixels to a PNG, TGA or BMP file, picked by the file's extension.
+   /// JPEG isn't supported: a .jpg or .jpeg path gives an `Unsupported` error.
+   pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), imagefmt::Error> {
+      image::write_rgba(path, self.width, self.height, &self.pixels)
+   }
+}
+
+
+
+impl SoftwareRenderer {
+   fn draw_figure(&mut self, points: &[Point], figure: &Figure) {
+      // Every subpath is closed for filling, so the last point connects back to the first
+      let mut edges = vec![];
+
+      for path in &figure.paths {
+         let path_points = &points[path.index..path.index + path.length];
+
+         for i in 0..path_points.len() {
+            let start = path_points[i];
+            let end = path_points[(i + 1) % path_points.len()];
+
+            if start.y != end.y && start.x.is_finite() && start.y.is_finite() && end.x.is_finite() && end.y.is_finite() {
+               edges.push(Edge::new(start, end));
+            }
+         }
+      }
+
+      if edges.len() == 0 || self.width == 0 || self.height == 0 {
+         return;
+      }
+
+      // Only rows and columns the figure touches need to be looked at
+      let top = edges.iter().fold(::std::f32::INFINITY, |top, edge| top.min(edge.top.y)).floor().max(0.0) as usize;
+      let bottom = edges.iter().fold(::std::f32::NEG_INFINITY, |bottom, edge| bottom.max(edge.bottom.y)).ceil().min(self.height as f32) as usize;
+      let left = edges.iter().fold(::std::f32::INFINITY, |left, edge| left.min(edge.top.x).min(edge.bottom.x)).floor().max(0.0) as usize;
+      let right = edges.iter().fold(::std::f32::NEG_INFINITY, |right, edge| right.max(edge.top.x).max(edge.bottom.x)).ceil().min(self.width as f32) as usize;
+
+      if top >= bottom || left >= right {
+         return;
+      }
+
+      edges.sort_by(|a, b| a.top.y.partial_cmp(&b.top.y).unwrap());
+
+      let fill_transform = figure.transform.inverse().unwrap_or(Matrix::identity());
+      let pattern_transform = pattern_inverse(&figure.fill);
+      let width = right - left;
+      let mut coverage = vec![0.0f32; width + 1];
+      let mut runs = vec![0.0f32; width + 1];
+      let mut crossings: Vec<(f32, i32)> = vec![];
+      let mut active_edges: Vec<&Edge> = vec![];
+      let mut next_edge = 0;
+
+      for y in top..bottom {
+         for value in coverage.iter_mut() { *value = 0.0; }
+         for value in runs.iter_mut() { *value = 0.0; }
+
+         // Keep track of the edges that pass through this row
+         while next_edge < edges.len() && edges[next_edge].top.y < (y + 1) as f32 {
+            active_edges.push(&edges[next_edge]);
+            next_edge += 1;
+         }
+         active_edges.retain(|edge| edge.bottom.y > y as f32);
+
+         for sample in 0..SAMPLES_PER_ROW {
+            let sample_y = y as f32 + (sample as f32 + 0.5) / SAMPLES_PER_ROW as f32;
+
+            crossings.clear();
+            for edge in &active_edges {
+               if edge.top.y <= sample_y && sample_y < edge.bottom.y {
+                  crossings.push((edge.x_at(sample_y) - left as f32, edge.winding));
+               }
+            }
+
+            crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+            // Walk across the row, keeping count of the winding number
+            let mut winding = 0;
+            let mut span_start = 0.0;
+
+            for &(x, direction) in &crossings {
+               let was_inside = is_inside(winding, figure.fill_rule);
+               winding += direction;
+               let is_now_inside = is_inside(winding, figure.fill_rule);
+
+               if !was_inside && is_now_inside {
+                  span_start = x;
+               } else if was_inside && !is_now_inside {
+                  add_span(&mut coverage, &mut runs, span_start, x, width, 1.0 / SAMPLES_PER_ROW as f32);
+               }
+            }
+         }
+
+         // Shade every pixel that's at least partly covered
+         let mut run = 0.0;
+
+         for column in 0..width {
+            run += runs[column];
+            let amount = (coverage[column] + run).min(1.0);
+
+            if amount > 1.0 / 255.0 {
+               let x = left + column;
+               let location = fill_transform * Point::new(x as f32 + 0.5, y as f32 + 0.5);
+               let color = paint(&figure.fill, location, pattern_transform);
+               self.blend(x, y, color, amount);
+            }
+         }
+      }
+   }
+
+
+   // Glyphs are rasterized in their own coordinates, then sampled with bilinear
+   // filtering for each pixel they cover, like the glyph atlas in the renderer
+   fn draw_text(&mut self, run: &TextRun, figure: &Figure) {
+      let canvas_to_glyph = match run.transform.inverse() {
+         Some(inverse) => inverse,
+         None => return,
+      };
+      let fill_transform = figure.transform.inverse().unwrap_or(Matrix::identity());
+      let pattern_transform = pattern_inverse(&figure.fill);
+
+      for glyph in &run.glyphs {
+         // The values covering the glyph's quad, which are its coverage or distance field
+         let (quad, values, glyph_width, glyph_height) = match self.text_rendering {
+            TextRendering::Bitmap => {
+               let bounds = match glyph.pixel_bounding_box() {
+                  Some(bounds) => bounds,
+                  None => continue,
+               };
+
+               let (glyph_width, glyph_height) = (bounds.width() as usize, bounds.height() as usize);
+               let mut coverage = vec![0.0f32; glyph_width * glyph_height];
+               glyph.draw(|x, y, value| coverage[y as usize * glyph_width + x as usize] = value);
+
+               let quad = Rect::new(bounds.min.x as f32, bounds.min.y as f32, glyph_width as f32, glyph_height as f32);
+               (quad, coverage, glyph_width, glyph_height)
+            }
+
+            TextRendering::DistanceField => {
+               let field = match run.font.distance_field(glyph) {
+                  Some(field) => field,
+                  None => continue,
+               };
+
+               let atlas = run.font.distance_field_atlas();
+               let (glyph_width, glyph_height) = (field.atlas.width as usize, field.atlas.height as usize);
+               let mut distances = Vec::with_capacity(glyph_width * glyph_height);
+
+               for row in 0..glyph_height {
+                  let start = (field.atlas.y as usize + row) * atlas.size as usize + field.atlas.x as usize;
+                  distances.extend(atlas.pixels[start..start + glyph_width].iter().map(|&value| value as f32 / 255.0));
+               }
+
+               (field.placed(glyph), distances, glyph_width, glyph_height)
+            }
+         };
+
+         // How much the distance changes across a pixel, to smooth the edge over
+         let texels_per_pixel = canvas_to_glyph.max_scale() * glyph_width as f32 / quad.width;
+         let pixel_distance = (texels_per_pixel / (2.0 * FIELD_SPREAD)).max(0.0001);
+
+         // The pixels the glyph's quad could touch
+         let (min_x, min_y, max_x, max_y) = (quad.x, quad.y, quad.x + quad.width, quad.y + quad.height);
+         let corners = [
+            run.transform * Point::new(min_x, min_y),
+            run.transform * Point::new(max_x, min_y),
+            run.transform * Point::new(max_x, max_y),
+            run.transform * Point::new(min_x, max_y),
+         ];
+         let top = corners.iter().fold(::std::f32::INFINITY, |top, corner| top.min(corner.y)).floor().max(0.0) as usize;
+         let bottom = corners.iter().fold(::std::f32::NEG_INFINITY, |bottom, corner| bottom.max(corner.y)).ceil().min(self.height as f32) as usize;
+         let left = corners.iter().fold(::std::f32::INFINITY, |left, corner| left.min(corner.x)).floor().max(0.0) as usize;
+         let right = corners.iter().fold(::std::f32::NEG_INFINITY, |right, corner| right.max(corner.x)).ceil().min(self.width as f32) as usize;
+
+         for y in top..bottom {
+            for x in left..right {
+               let center = Point::new(x as f32 + 0.5, y as f32 + 0.5);
+               let location = canvas_to_glyph * center;
+
+               if location.x < min_x || location.x >= max_x || location.y < min_y || location.y >= max_y {
+                  continue;
+               }
+
+               let value = bilinear(
+                  &values, glyph_width, glyph_height,
+                  (location.x - min_x) * glyph_width as f32 / quad.width - 0.5,
+                  (location.y - min_y) * glyph_height as f32 / quad.height - 0.5,
+               );
+
+               let amount = match self.text_rendering {
+                  TextRendering::Bitmap => value,
+                  TextRendering::DistanceField => ((value - 0.5) / pixel_distance + 0.5).max(0.0).min(1.0),
+               };
+
+               if amount > 0.0 {
+                  let color = paint(&figure.fill, fill_transform * center, pattern_transform);
+                  self.blend(x, y, color, amount);
+               }
+            }
+         }
+      }
+   }
+
+
+   // Source-over blending
+   fn blend(&mut self, x: usize, y: usize, color: Color, coverage: f32) {
+      let index = (y * self.width as usize + x) * 4;
+      let source_alpha = color.3.max(0.0).min(1.0) * coverage;
+
+      let destination = &mut self.pixels[index..index + 4];
+      let destination_alpha = destination[3] as f32 / 255.0;
+      let alpha = source_alpha + destination_alpha * (1.0 - source_alpha);
+
+      if alpha <= 0.0 {
+         return;
+      }
+
+      let mix = |source: f32, destination: u8| {
+         (source.max(0.0).min(1.0) * source_alpha + destination as f32 / 255.0 * destination_alpha * (1.0 - source_alpha)) / alpha
+      };
+
+      destination[0] = to_byte(mix(color.0, destination[0]));
+      destination[1] = to_byte(mix(color.1, destination[1]));
+      destination[2] = to_byte(mix(color.2, destination[2]));
+      destination[3] = to_byte(alpha);
+   }
+}
+
+
+
+// One side of a polygon, stored top to bottom
+struct Edge {
+   top: Point,
+   bottom: Point,
+   winding: i32, // 1 if the path goes down along the edge, -1 if it goes up
+}
+
+
+impl Edge {
+   fn new(start: Point, end: Point) -> Edge {
+      if start.y < end.y {
+         Edge {top: start, bottom: end, winding: 1}
+      } else {
+         Edge {top: end, bottom: start, winding: -1}
+      }
+   }
+
+
+   fn x_at(&self, y: f32) -> f32 {
+      self.top.x + (y - self.top.y) * (self.bottom.x - self.top.x) / (self.bottom.y - self.top.y)
+   }
+}
+
+
+
+fn is_inside(winding: i32, fill_rule: FillRule) -> bool {
+   match fill_rule {
+      FillRule::NonZero => winding != 0,
+      FillRule::EvenOdd => winding % 2 != 0,
+   }
+}
+
+
+
+// Add `weight` worth of coverage between `start` and `end`. Pixels the span only
+// partly covers get a share of the weight, and whole pixels are recorded as a run
+// that starts at one pixel and ends at another, to be added up across the row later.
+fn add_span(coverage: &mut [f32], runs: &mut [f32], start: f32, end: f32, width: usize, weight: f32) {
+   let start = start.max(0.0).min(width as f32);
+   let end = end.max(0.0).min(width as f32);
+
+   if end <= start {
+      return;
+   }
+
+   let first = start.floor() as usize;
+   let last = end.floor() as usize;
+
+   if first == last {
+      coverage[first] += (end - start) * weight;
+      return;
+   }
+
+   coverage[first] += (first as f32 + 1.0 - start) * weight;
+   runs[first + 1] += weight;
+   runs[last] -= weight;
+   coverage[last] += (end - last as f32) * weight;
+}
+
+
+
+// Maps the coordinate system a figure was filled in to its pattern's, if it's filled with one.
+// Worked out once per figure rather than for every pixel.
+fn pattern_inverse(fill: &Fill) -> Matrix {
+   match *fill {
+      Fill::Pattern(ref pattern) => pattern.pattern_transform().inverse().unwrap_or(Matrix::identity()),
+      _ => Matrix::identity(),
+   }
+}
+
+
+// The color of a fill at a location in the coordinate system the figure was filled in.
+// `pattern_transform` comes from `pattern_inverse`. Matches the renderer's fragment shader.
+fn paint(fill: &Fill, location: Point, pattern_transform: Matrix) -> Color {
+   match *fill {
+      Fill::SolidColor(color) => color,
+
+      Fill::LinearGradient(begin, end, ref gradient) => {
+         let difference = Point::new(end.x - begin.x, end.y - begin.y);
+         let length_squared = difference.x * difference.x + difference.y * difference.y;
+         let position = ((location.x - begin.x) * difference.x + (location.y - begin.y) * difference.y) / length_squared;
+         gradient.color_at(position)
+      },
+
+      Fill::RadialGradient(begin, begin_radius, end, end_radius, ref gradient) => {
+         match radial_gradient_position(location, begin, begin_radius, end, end_radius) {
+            Some(position) => gradient.color_at(position),
+            None => (0.0, 0.0, 0.0, 0.0),
+         }
+      },
+
+      Fill::ConicGradient(center, start_angle, ref gradient) => {
+         let angle = (location.y - center.y).atan2(location.x - center.x) - start_angle;
+         let position = angle / (2.0 * consts::PI);
+         gradient.color_at(position - position.floor())
+      },
+
+      Fill::Image(ref image, corner, width, height) => {
+         let texture_location = Point::new((location.x - corner.x) / width, (location.y - corner.y) / height);
+         sample(image, texture_location)
+      },
+
+      Fill::Pattern(ref pattern) => {
+         let image = pattern.image();
+         let pattern_location = pattern_transform * location;
+         let texture_location = Point::new(pattern_location.x / image.width() as f32, pattern_location.y / image.height() as f32);
+
+         let (repeats_x, repeats_y) = match pattern.repetition() {
+            Repetition::Repeat => (true, true),
+            Repetition::RepeatX => (true, false),
+            Repetition::RepeatY => (false, true),
+            Repetition::NoRepeat => (false, false),
+         };
+
+         if (!repeats_x && (texture_location.x < 0.0 || texture_location.x >= 1.0)) ||
+            (!repeats_y && (texture_location.y < 0.0 || texture_location.y >= 1.0)) {
+            (0.0, 0.0, 0.0, 0.0)
+         } else {
+            sample(image, texture_location)
+         }
+      },
+   }
+}
+
+
+
+// Same as radial_gradient_position in the renderer's fragment shader
+fn radial_gradient_position(location: Point, begin: Point, begin_radius: f32, end: Point, end_radius: f32) -> Option<f32> {
+   let center_difference = Point::new(end.x - begin.x, end.y - begin.y);
+   let location_difference = Point::new(location.x - begin.x, location.y - begin.y);
+   let radius_difference = end_radius - begin_radius;
+
+   let a = center_difference.x * center_difference.x + center_difference.y * center_difference.y - radius_difference * radius_difference;
+   let b = location_difference.x * center_difference.x + location_difference.y * center_difference.y + begin_radius * radius_difference;
+   let c = location_difference.x * location_difference.x + location_difference.y * location_difference.y - begin_radius * begin_radius;
+
+   let valid = |position: f32| begin_radius + position * radius_difference >= 0.0;
+
+   if a.abs() < 0.0001 {
+      if b == 0.0 {
+         return None;
+      }
+
+      let position = c / (2.0 * b);
+      return if valid(position) { Some(position) } else { None };
+   }
+
+   let discriminant = b * b - a * c;
+
+   if discriminant < 0.0 {
+      return None;
+   }
+
+   let root = discriminant.sqrt();
+   let larger = ((b + root) / a).max((b - root) / a);
+   let smaller = ((b + root) / a).min((b - root) / a);
+
+   if valid(larger) {
+      Some(larger)
+   } else if valid(smaller) {
+      Some(smaller)
+   } else {
+      None
+   }
+}
+
+
+
+// Nearest neighbor sampling with the texture repeating in both directions,
+// like the OpenGL textures images are uploaded to
+fn sample(image: &Image, texture_location: Point) -> Color {
+   if image.width() == 0 || image.height() == 0 || !texture_location.x.is_finite() || !texture_location.y.is_finite() {
+      return (0.0, 0.0, 0.0, 0.0);
+   }
+
+   let u = texture_location.x - texture_location.x.floor();
+   let v = texture_location.y - texture_location.y.floor();
+   let x = ((u * image.width() as f32) as u32).min(image.width() - 1);
+   let y = ((v * image.height() as f32) as u32).min(image.height() - 1);
+
+   image.pixel(x, y)
+}
+
+
+
+// Blend the four values around (x, y) in a width by height grid, clamping to the edges
+fn bilinear(values: &[f32], width: usize, height: usize, x: f32, y: f32) -> f32 {
+   let value = |x: f32, y: f32| {
+      let x = (x.max(0.0) as usize).min(width - 1);
+      let y = (y.max(0.0) as usize).min(height - 1);
+      values[y * width + x]
+   };
+
+   let (left, top) = (x.floor(), y.floor());
+   let (across, down) = (x - left, y - top);
+
+   let upper = value(left, top) * (1.0 - across) + value(left + 1.0, top) * across;
+   let lower = value(left, top + 1.0) * (1.0 - across) + value(left + 1.0, top + 1.0) * across;
+   upper * (1.0 - down) + lower * down
+}
+
+
+
+fn to_byte(value: f32) -> u8 {
+   (value.max(0.0).min(1.0) * 255.0 + 0.5) as u8
+}
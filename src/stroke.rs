@@ -0,0 +1,363 @@
+use std::f32::consts;
+use super::geometry::Point;
+
+
+// Dash patterns that would cut a line into more pieces than this draw it solid instead
+const MAX_DASHES: f32 = 100_000.0;
+
+
+/// The shape drawn where two lines of a stroke meet
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LineJoin {
+   /// Extend the outer edges until they meet in a point, unless that point is further out than the miter limit
+   Miter,
+   Round,
+   /// Cut the corner off with a straight line
+   Bevel,
+}
+
+
+/// The shape drawn at the ends of an open stroke
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LineCap {
+   /// End exactly at the end point
+   Butt,
+   Round,
+   /// Extend past the end point by half the line width
+   Square,
+}
+
+
+#[derive(Debug, Clone)]
+pub(crate) struct StrokeStyle {
+   pub width: f32,
+   pub join: LineJoin,
+   pub cap: LineCap,
+   pub miter_limit: f32,
+   pub dash: Vec<f32>, // Alternating dash and gap lengths. Empty for a solid line.
+   pub dash_offset: f32,
+}
+
+
+impl StrokeStyle {
+   pub fn new() -> StrokeStyle {
+      StrokeStyle {
+         width: 1.0,
+         join: LineJoin::Miter,
+         cap: LineCap::Butt,
+         miter_limit: 10.0,
+         dash: vec![],
+         dash_offset: 0.0,
+      }
+   }
+}
+
+
+
+// Turn a line through `points` into the polygons that make up its outline,
+// splitting it into dashes first if the style has a dash pattern.
+pub(crate) fn stroke_polyline(points: &[Point], closed: bool, style: &StrokeStyle, tolerance: f32, output: &mut Vec<Vec<Point>>) {
+   match split_dashes(points, closed, &style.dash, style.dash_offset, tolerance) {
+      Some(dashes) => for dash in dashes {
+         stroke_solid(&dash, false, style, tolerance, output);
+      },
+      None => stroke_solid(points, closed, style, tolerance, output),
+   }
+}
+
+
+
+// Every polygon is convex: one per line segment, one per join and one per cap.
+// They overlap, so they need to be filled with the non-zero rule, and they're all wound
+// the same way so that where a stroke crosses itself the windings add up instead of cancelling.
+// `tolerance` is how far round joins and caps may stray from a true circle.
+fn stroke_solid(points: &[Point], closed: bool, style: &StrokeStyle, tolerance: f32, output: &mut Vec<Vec<Point>>) {
+   let half_width = style.width / 2.0;
+
+   if !(half_width > 0.0) {
+      return;
+   }
+
+   // Zero length segments have no direction, so drop them
+   let mut points: Vec<Point> = points.iter().fold(vec![], |mut points, &point| {
+      if points.last().map_or(true, |&last| !same_point(last, point)) {
+         points.push(point);
+      }
+      points
+   });
+
+   if closed && points.len() > 1 && same_point(points[0], points[points.len() - 1]) {
+      points.pop();
+   }
+
+   // A lone point has no direction, but round and square caps still give it a dot. That's how dotted lines get drawn.
+   if points.len() == 1 {
+      if !closed {
+         cap(points[0], Point::new(1.0, 0.0), style.cap, half_width, tolerance, output);
+         cap(points[0], Point::new(-1.0, 0.0), style.cap, half_width, tolerance, output);
+      }
+      return;
+   }
+
+   if points.len() < 2 {
+      return;
+   }
+
+   let segment_count = if closed { points.len() } else { points.len() - 1 };
+   let directions: Vec<Point> = (0..segment_count).map(|i| {
+      direction(points[i], points[(i + 1) % points.len()])
+   }).collect();
+
+   // The body of each line segment
+   for (i, &dir) in directions.iter().enumerate() {
+      let start = points[i];
+      let end = points[(i + 1) % points.len()];
+      let normal = Point::new(-dir.y * half_width, dir.x * half_width);
+
+      output.push(vec![
+         Point::new(start.x + normal.x, start.y + normal.y),
+         Point::new(end.x + normal.x, end.y + normal.y),
+         Point::new(end.x - normal.x, end.y - normal.y),
+         Point::new(start.x - normal.x, start.y - normal.y),
+      ]);
+   }
+
+   // The corners between segments
+   let joins = if closed { 0..segment_count } else { 1..segment_count };
+
+   for i in joins {
+      let incoming = directions[(i + segment_count - 1) % segment_count];
+      let outgoing = directions[i];
+      join(points[i], incoming, outgoing, style, half_width, tolerance, output);
+   }
+
+   // The ends
+   if !closed {
+      let last = points.len() - 1;
+      cap(points[0], Point::new(-directions[0].x, -directions[0].y), style.cap, half_width, tolerance, output);
+      cap(points[last], directions[segment_count - 1], style.cap, half_width, tolerance, output);
+   }
+}
+
+
+
+// Cut a line into the pieces that are "on" in the dash pattern, measuring along its length.
+// The pattern starts over on every call, shifted forwards by `offset`.
+// None if the line should be drawn solid instead: a pattern with no length at all is ignored, like in
+// HTML canvas, and so is one too fine to see at `tolerance` or that would cut the line into too many dashes.
+fn split_dashes(points: &[Point], closed: bool, pattern: &[f32], offset: f32, tolerance: f32) -> Option<Vec<Vec<Point>>> {
+   let pattern_length: f32 = pattern.iter().sum();
+
+   if !(pattern_length > tolerance) {
+      return None;
+   }
+
+   let segment_count = if closed { points.len() } else { points.len().saturating_sub(1) };
+   let line_length: f32 = (0..segment_count).map(|i| distance(points[i], points[(i + 1) % points.len()])).sum();
+
+   if !(line_length / pattern_length * pattern.len() as f32 <= MAX_DASHES) {
+      return None;
+   }
+
+   let mut dashes = vec![];
+
+   if points.len() == 0 {
+      return Some(dashes);
+   }
+
+   // Find where in the pattern the line starts. Rounding can leave a little over after the last length, so stop there.
+   let mut index = 0;
+   let mut remaining = pattern[0];
+   let mut skip = offset.rem_euclid(pattern_length);
+
+   while skip > remaining && index + 1 < pattern.len() {
+      skip -= remaining;
+      index += 1;
+      remaining = pattern[index];
+   }
+   remaining = (remaining - skip).max(0.0);
+
+   let mut dash = if index % 2 == 0 { Some(vec![points[0]]) } else { None };
+
+   for i in 0..segment_count {
+      let start = points[i];
+      let end = points[(i + 1) % points.len()];
+      let (dx, dy) = (end.x - start.x, end.y - start.y);
+      let length = (dx * dx + dy * dy).sqrt();
+      let mut travelled = 0.0;
+      let mut cycle_start = -1.0;
+
+      // Walk along the segment, switching between dash and gap whenever the current one runs out
+      while length - travelled > remaining {
+         travelled += remaining;
+         let t = travelled / length;
+         let point = Point::new(start.x + dx * t, start.y + dy * t);
+
+         dash = match dash.take() {
+            Some(mut points) => {
+               points.push(point);
+               dashes.push(points);
+               None
+            },
+            None => Some(vec![point]),
+         };
+
+         index = (index + 1) % pattern.len();
+         remaining = pattern[index];
+
+         // Far enough along a long segment, lengths too short to move it forwards would go round forever
+         if index == 0 {
+            if travelled == cycle_start {
+               return None;
+            }
+            cycle_start = travelled;
+         }
+      }
+
+      remaining -= length - travelled;
+
+      if let Some(ref mut points) = dash {
+         points.push(end);
+      }
+   }
+
+   if let Some(points) = dash {
+      dashes.push(points);
+   }
+
+   Some(dashes)
+}
+
+
+
+fn join(corner: Point, incoming: Point, outgoing: Point, style: &StrokeStyle, half_width: f32, tolerance: f32, output: &mut Vec<Vec<Point>>) {
+   let cross = incoming.x * outgoing.y - incoming.y * outgoing.x;
+   let dot = incoming.x * outgoing.x + incoming.y * outgoing.y;
+
+   // Going straight on needs no join
+   if cross.abs() < 1e-6 && dot > 0.0 {
+      return;
+   }
+
+   // The join goes on the outside of the turn
+   let side = if cross > 0.0 { -half_width } else { half_width };
+   let before = Point::new(corner.x - incoming.y * side, corner.y + incoming.x * side);
+   let after = Point::new(corner.x - outgoing.y * side, corner.y + outgoing.x * side);
+
+   let mut polygon = match style.join {
+      LineJoin::Round => {
+         let start_angle = (before.y - corner.y).atan2(before.x - corner.x);
+         let end_angle = (after.y - corner.y).atan2(after.x - corner.x);
+         fan(corner, half_width, start_angle, end_angle, side > 0.0, tolerance)
+      },
+
+      LineJoin::Miter => {
+         // The miter's length compared to the line width is 1 / sin(angle between the lines / 2)
+         let half_turn_cos = ((1.0 + dot) / 2.0).max(0.0).sqrt();
+
+         if half_turn_cos > 0.0 && 1.0 / half_turn_cos <= style.miter_limit {
+            let bisector = direction(Point::new(0.0, 0.0), Point::new(
+               (before.x - corner.x) + (after.x - corner.x),
+               (before.y - corner.y) + (after.y - corner.y),
+            ));
+            let length = half_width / half_turn_cos;
+            let tip = Point::new(corner.x + bisector.x * length, corner.y + bisector.y * length);
+            vec![corner, before, tip, after]
+         } else {
+            vec![corner, before, after]
+         }
+      },
+
+      LineJoin::Bevel => vec![corner, before, after],
+   };
+
+   // Turning the other way mirrors the join, so wind it back the same way as the segments and caps
+   if cross > 0.0 {
+      polygon.reverse();
+   }
+
+   output.push(polygon);
+}
+
+
+
+// `outward` points away from the line, along it
+fn cap(end: Point, outward: Point, style: LineCap, half_width: f32, tolerance: f32, output: &mut Vec<Vec<Point>>) {
+   let normal = Point::new(-outward.y * half_width, outward.x * half_width);
+
+   match style {
+      LineCap::Butt => {},
+
+      LineCap::Square => {
+         let extension = Point::new(outward.x * half_width, outward.y * half_width);
+
+         output.push(vec![
+            Point::new(end.x + normal.x, end.y + normal.y),
+            Point::new(end.x + normal.x + extension.x, end.y + normal.y + extension.y),
+            Point::new(end.x - normal.x + extension.x, end.y - normal.y + extension.y),
+            Point::new(end.x - normal.x, end.y - normal.y),
+         ]);
+      },
+
+      LineCap::Round => {
+         let start_angle = normal.y.atan2(normal.x);
+         output.push(fan(end, half_width, start_angle, start_angle - consts::PI, true, tolerance));
+      },
+   }
+}
+
+
+
+// A pie slice from `start_angle` to `end_angle`, taking the short way around unless
+// the two are exactly opposite, in which case `anticlockwise` picks the direction
+fn fan(center: Point, radius: f32, start_angle: f32, end_angle: f32, anticlockwise: bool, tolerance: f32) -> Vec<Point> {
+   let mut sweep = end_angle - start_angle;
+
+   while sweep > consts::PI { sweep -= 2.0 * consts::PI; }
+   while sweep < -consts::PI { sweep += 2.0 * consts::PI; }
+
+   if (sweep.abs() - consts::PI).abs() < 1e-4 {
+      sweep = if anticlockwise { -consts::PI } else { consts::PI };
+   }
+
+   let step = if radius > tolerance {
+      2.0 * (1.0 - tolerance / radius).acos()
+   } else {
+      consts::PI / 2.0
+   };
+   let segments = ((sweep.abs() / step).ceil() as usize).max(1).min(1000);
+
+   let mut points = vec![center];
+
+   for i in 0..segments + 1 {
+      let angle = start_angle + sweep * i as f32 / segments as f32;
+      points.push(Point::new(center.x + radius * angle.cos(), center.y + radius * angle.sin()));
+   }
+
+   points
+}
+
+
+
+fn direction(from: Point, to: Point) -> Point {
+   let (dx, dy) = (to.x - from.x, to.y - from.y);
+   let length = (dx * dx + dy * dy).sqrt();
+
+   if length > 0.0 {
+      Point::new(dx / length, dy / length)
+   } else {
+      Point::new(0.0, 0.0)
+   }
+}
+
+
+
+fn distance(a: Point, b: Point) -> f32 {
+   ((b.x - a.x) * (b.x - a.x) + (b.y - a.y) * (b.y - a.y)).sqrt()
+}
+
+
+
+fn same_point(a: Point, b: Point) -> bool {
+   (a.x - b.x).abs() < 1e-6 && (a.y - b.y).abs() < 1e-6
+}
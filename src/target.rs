@@ -0,0 +1,156 @@
+use gl;
+use gl::types::*;
+
+use std::rc::Rc;
+use std::path::Path;
+use std::os::raw::c_void;
+use super::Image;
+use super::image;
+use imagefmt;
+
+
+/// An offscreen framebuffer that a `Renderer` can draw canvases into instead of the window.
+/// What's drawn ends up in an `Image`, which can be used to fill shapes like any other image.
+/// Useful for caching layers that rarely change, or for making thumbnails.
+///
+/// Creating, clearing and drawing into a target requires a current OpenGL context.
+pub struct RenderTarget {
+   framebuffer: GLuint,
+   stencil_buffer: GLuint,
+   image: Rc<Image>,
+}
+
+
+
+impl Drop for RenderTarget {
+   fn drop(&mut self) {
+      unsafe {
+         gl::DeleteFramebuffers(1, &self.framebuffer);
+         gl::DeleteRenderbuffers(1, &self.stencil_buffer);
+      }
+   }
+}
+
+
+
+impl RenderTarget {
+   /// Create a target `width` by `height` pixels in size. It starts out transparent.
+   pub fn new(width: u32, height: u32) -> RenderTarget {
+      let image = Image::gpu_only(width, height);
+      let mut framebuffer = 0;
+      let mut stencil_buffer = 0;
+
+      unsafe {
+         let previous = bind_framebuffer(0);
+
+         // The stencil buffer is needed to draw concave shapes. A combined depth and
+         // stencil buffer is the format every OpenGL implementation is sure to support.
+         gl::GenRenderbuffers(1, &mut stencil_buffer);
+         gl::BindRenderbuffer(gl::RENDERBUFFER, stencil_buffer);
+         gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width as i32, height as i32);
+         gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+
+         gl::GenFramebuffers(1, &mut framebuffer);
+         gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+         gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, image.texture_id(), 0);
+         gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, stencil_buffer);
+
+         let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+         if status != gl::FRAMEBUFFER_COMPLETE {
+            panic!("Couldn't create a render target framebuffer. Status: {:#x}", status);
+         }
+
+         bind_framebuffer(previous);
+      }
+
+      let target = RenderTarget {
+         framebuffer: framebuffer,
+         stencil_buffer: stencil_buffer,
+         image: image,
+      };
+
+      target.clear(0.0, 0.0, 0.0, 0.0);
+      target
+   }
+
+
+   /// Set every pixel to a color and reset the stencil buffer
+   pub fn clear(&self, red: f32, green: f32, blue: f32, alpha: f32) {
+      self.with_bound(|| unsafe {
+         gl::ClearColor(red, green, blue, alpha);
+         gl::StencilMask(0xff);
+         gl::Clear(gl::COLOR_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+      });
+   }
+
+
+   /// The image everything is drawn into. Pass it to `Canvas::fill_image` or `Pattern::new`.
+   /// It keeps up with whatever is drawn into the target afterwards.
+   pub fn image(&self) -> Rc<Image> {
+      self.image.clone()
+   }
+
+
+   /// Width in pixels
+   pub fn width(&self) -> u32 {
+      self.image.width()
+   }
+
+
+   /// Height in pixels
+   pub fn height(&self) -> u32 {
+      self.image.height()
+   }
+
+
+   /// Read back what has been drawn as RGBA pixels, 8 bits per channel, starting with the top row
+   pub fn read_pixels(&self) -> Vec<u8> {
+      let mut pixels = vec![0u8; self.width() as usize * self.height() as usize * 4];
+
+      // Targets are drawn upside down (see `Renderer::draw_canvas_to`),
+      // so OpenGL's bottom row is the top of the canvas and no flipping is needed
+      self.with_bound(|| unsafe {
+         gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+         gl::ReadPixels(0, 0, self.width() as i32, self.height() as i32, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_mut_ptr() as *mut c_void);
+      });
+
+      pixels
+   }
+
+
+   /// Save what has been drawn to a PNG, TGA or BMP file, picked by the file's extension.
+   /// JPEG isn't supported: a .jpg or .jpeg path gives an `Unsupported` error.
+   pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), imagefmt::Error> {
+      image::write_rgba(path, self.width(), self.height(), &self.read_pixels())
+   }
+}
+
+
+
+impl RenderTarget {
+   // Run `draw` with the target bound as the framebuffer and the viewport covering it,
+   // putting back whatever framebuffer and viewport were in use before
+   pub(crate) fn with_bound<F: FnOnce()>(&self, draw: F) {
+      unsafe {
+         let mut viewport = [0; 4];
+         gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+         let previous = bind_framebuffer(self.framebuffer);
+         gl::Viewport(0, 0, self.width() as i32, self.height() as i32);
+
+         draw();
+
+         bind_framebuffer(previous);
+         gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+      }
+   }
+}
+
+
+
+// Bind a framebuffer for drawing and reading, returning the one that was bound before
+unsafe fn bind_framebuffer(framebuffer: GLuint) -> GLuint {
+   let mut previous = 0;
+   gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous);
+   gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+   previous as GLuint
+}
@@ -0,0 +1,255 @@
+use std::rc::Rc;
+use super::Font;
+
+
+/// How the lines of a paragraph line up
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextAlign {
+   Left,
+   Center,
+   Right,
+   /// Stretch the spaces between words so every line but the last of each paragraph fills the width
+   Justify,
+}
+
+
+/// Wraps text into lines that fit in a width. Lay text out with `layout`,
+/// then draw it with `Canvas::fill_text_layout`.
+#[derive(Debug, Clone)]
+pub struct Paragraph {
+   font: Rc<Font>,
+   size: f32,
+   max_width: f32,
+   align: TextAlign,
+   line_height: Option<f32>, // Defaults to the font's own line spacing
+   max_lines: Option<usize>,
+   ellipsis: String,
+}
+
+
+/// Text broken into lines by `Paragraph::layout`. Everything is measured
+/// from the top left corner of the paragraph.
+#[derive(Debug, Clone)]
+pub struct TextLayout {
+   pub lines: Vec<TextLine>,
+   /// The width of the widest line
+   pub width: f32,
+   /// From the top of the first line to the bottom of the last
+   pub height: f32,
+   /// Whether text was cut off to keep within the maximum number of lines
+   pub truncated: bool,
+   pub(crate) font: Rc<Font>,
+   pub(crate) size: f32,
+}
+
+
+/// One line of a `TextLayout`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLine {
+   /// The text on the line, with the ellipsis if it was cut short
+   pub text: String,
+   /// Pieces of the line and where each starts. Justified lines have one per word, others a single one.
+   pub spans: Vec<(f32, String)>,
+   /// Where the line's baseline is, down from the top of the paragraph
+   pub baseline: f32,
+   /// Where the line starts, after alignment
+   pub x: f32,
+   pub width: f32,
+}
+
+
+
+impl Paragraph {
+   /// Lines are wrapped at spaces to fit in `max_width`, which can be infinite to only break at newlines.
+   /// A width that's zero, negative or not a number puts every character on a line of its own.
+   pub fn new(font: Rc<Font>, size: f32, max_width: f32) -> Paragraph {
+      Paragraph {
+         font: font,
+         size: size,
+         max_width: if max_width > 0.0 { max_width } else { 0.0 },
+         align: TextAlign::Left,
+         line_height: None,
+         max_lines: None,
+         ellipsis: "…".to_string(),
+      }
+   }
+
+
+   /// Defaults to `TextAlign::Left`
+   pub fn align(mut self, align: TextAlign) -> Self {
+      self.align = align;
+      self
+   }
+
+
+   /// Set the distance from one baseline to the next. Defaults to the font's ascent, descent and line gap added up.
+   pub fn line_height(mut self, height: f32) -> Self {
+      self.line_height = Some(height);
+      self
+   }
+
+
+   /// Cut the text off after this many lines, ending the last one with the ellipsis
+   pub fn max_lines(mut self, lines: usize) -> Self {
+      self.max_lines = Some(lines);
+      self
+   }
+
+
+   /// What to end text that's cut off with. Defaults to "…".
+   pub fn ellipsis(mut self, ellipsis: &str) -> Self {
+      self.ellipsis = ellipsis.to_string();
+      self
+   }
+
+
+   pub fn layout(&self, text: &str) -> TextLayout {
+      let metrics = self.font.measure("", self.size);
+      let line_height = self.line_height.unwrap_or(metrics.ascent + metrics.descent + metrics.line_gap);
+
+      // Each line, and whether it ends a paragraph
+      let mut lines: Vec<(String, bool)> = vec![];
+
+      for paragraph in text.split('\n') {
+         let start = lines.len();
+         self.wrap(paragraph, &mut lines);
+         if lines.len() == start {
+            lines.push((String::new(), true));
+         }
+         lines.last_mut().unwrap().1 = true;
+      }
+
+      let mut truncated = false;
+
+      if let Some(max_lines) = self.max_lines {
+         if lines.len() > max_lines {
+            lines.truncate(max_lines);
+            truncated = true;
+
+            if let Some(last) = lines.last_mut() {
+               *last = (self.with_ellipsis(&last.0), true);
+            }
+         }
+      }
+
+      // Line up the lines in the width, or the widest line if the width is infinite
+      let widths: Vec<f32> = lines.iter().map(|&(ref line, _)| self.width_of(line)).collect();
+      let width = widths.iter().fold(0.0f32, |widest, &width| widest.max(width));
+      let box_width = if self.max_width.is_finite() { self.max_width } else { width };
+
+      let lines = lines.into_iter().zip(widths).enumerate().map(|(index, ((text, ends_paragraph), line_width))| {
+         let words: Vec<&str> = text.split(' ').filter(|word| word.len() > 0).collect();
+         let justify = self.align == TextAlign::Justify && !ends_paragraph && words.len() > 1;
+
+         let (x, spans) = if justify {
+            let word_widths: Vec<f32> = words.iter().map(|word| self.width_of(word)).collect();
+            let gap = (box_width - word_widths.iter().sum::<f32>()) / (words.len() - 1) as f32;
+            let mut position = 0.0;
+
+            let spans = words.iter().zip(word_widths).map(|(word, word_width)| {
+               let span = (position, word.to_string());
+               position += word_width + gap;
+               span
+            }).collect();
+
+            (0.0, spans)
+         } else {
+            let x = match self.align {
+               TextAlign::Left | TextAlign::Justify => 0.0,
+               TextAlign::Center => (box_width - line_width) / 2.0,
+               TextAlign::Right => box_width - line_width,
+            };
+
+            (x, vec![(x, text.clone())])
+         };
+
+         TextLine {
+            width: if justify { box_width } else { line_width },
+            text: text,
+            spans: spans,
+            baseline: metrics.ascent + index as f32 * line_height,
+            x: x,
+         }
+      }).collect::<Vec<TextLine>>();
+
+      TextLayout {
+         height: if lines.len() > 0 { (lines.len() - 1) as f32 * line_height + metrics.ascent + metrics.descent } else { 0.0 },
+         lines: lines,
+         width: width,
+         truncated: truncated,
+         font: self.font.clone(),
+         size: self.size,
+      }
+   }
+}
+
+
+
+impl Paragraph {
+   // Break a paragraph without newlines into lines that fit, greedily
+   fn wrap(&self, paragraph: &str, lines: &mut Vec<(String, bool)>) {
+      let mut line = String::new();
+
+      for word in paragraph.split(' ').filter(|word| word.len() > 0) {
+         let candidate = if line.len() == 0 { word.to_string() } else { format!("{} {}", line, word) };
+
+         if self.width_of(&candidate) <= self.max_width {
+            line = candidate;
+            continue;
+         }
+
+         if line.len() > 0 {
+            lines.push((line, false));
+         }
+
+         // Words too long for a line of their own are broken wherever they have to be
+         let mut rest = word.to_string();
+         while rest.len() > 0 && self.width_of(&rest) > self.max_width {
+            let (first, second) = self.split_to_fit(&rest, "");
+            lines.push((first, false));
+            rest = second;
+         }
+
+         line = rest;
+      }
+
+      if line.len() > 0 {
+         lines.push((line, false));
+      }
+   }
+
+
+   // Shorten a line until it fits with the ellipsis on the end
+   fn with_ellipsis(&self, line: &str) -> String {
+      let (fits, _) = self.split_to_fit(line, &self.ellipsis);
+      format!("{}{}", fits.trim_end(), self.ellipsis)
+   }
+
+
+   // Split text at the last character where it and `suffix` still fit in the width. At least one character
+   // goes in the first part so that wrapping always moves forwards, unless `suffix` is given.
+   fn split_to_fit(&self, text: &str, suffix: &str) -> (String, String) {
+      let available = self.max_width - self.width_of(suffix);
+      let characters: Vec<char> = text.chars().collect();
+      let metrics = self.font.measure(text, self.size);
+
+      // The first `count` characters end where the next one starts
+      let end = |count: usize| if count < characters.len() { metrics.positions[count] } else { metrics.width };
+      let mut count = characters.len();
+
+      while count > 0 && end(count) > available {
+         count -= 1;
+      }
+
+      if count == 0 && suffix.len() == 0 {
+         count = 1.min(characters.len());
+      }
+
+      (characters[..count].iter().collect(), characters[count..].iter().collect())
+   }
+
+
+   fn width_of(&self, text: &str) -> f32 {
+      self.font.measure(text, self.size).width
+   }
+}
@@ -0,0 +1,605 @@
+// Golden image tests. Each test draws a canvas with the software renderer and
+// compares it to a reference PNG in tests/golden.
+//
+// When a test fails, the rendered image and a diff highlighting the pixels that
+// changed are written to target/golden-failures. To accept new output as the
+// reference, run the tests with PICASSO_UPDATE_GOLDEN=1 and check in the PNGs.
+
+extern crate picasso;
+extern crate imagefmt;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::f32::consts;
+use picasso::{Canvas, SoftwareRenderer, Gradient, Spread, FillRule, LineJoin, LineCap, Image, Pattern, Repetition, Matrix, Font, Paragraph, TextAlign, TextRendering, FontFamily};
+
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 128;
+
+// How far apart, out of 255, a channel may be from the reference
+const TOLERANCE: u8 = 2;
+
+
+
+fn check(name: &str, canvas: &Canvas) {
+   check_with(name, canvas, SoftwareRenderer::new(WIDTH, HEIGHT));
+}
+
+
+// Compare with a renderer that's been set up differently
+fn check_with(name: &str, canvas: &Canvas, mut renderer: SoftwareRenderer) {
+   renderer.clear(1.0, 1.0, 1.0, 1.0);
+   renderer.draw_canvas(canvas);
+
+   let manifest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+   let reference_path = manifest.join("tests").join("golden").join(format!("{}.png", name));
+
+   if env::var_os("PICASSO_UPDATE_GOLDEN").is_some() {
+      renderer.save(&reference_path).unwrap();
+      return;
+   }
+
+   let failures = manifest.join("target").join("golden-failures");
+   let actual_path = failures.join(format!("{}.actual.png", name));
+   let diff_path = failures.join(format!("{}.diff.png", name));
+
+   let reference = match imagefmt::read(&reference_path, imagefmt::ColFmt::RGBA) {
+      Ok(reference) => reference,
+      Err(error) => {
+         fs::create_dir_all(&failures).unwrap();
+         renderer.save(&actual_path).unwrap();
+         panic!("couldn't read {}: {:?}. The rendered image is in {}", reference_path.display(), error, actual_path.display());
+      }
+   };
+
+   assert_eq!((reference.w as u32, reference.h as u32), (WIDTH, HEIGHT), "{} is the wrong size", reference_path.display());
+
+   // Show unchanged pixels faded out and changed ones in red
+   let mut diff = Vec::with_capacity(reference.buf.len());
+   let mut mismatches = 0;
+
+   for (actual, expected) in renderer.pixels().chunks(4).zip(reference.buf.chunks(4)) {
+      let matches = actual.iter().zip(expected.iter()).all(|(&a, &b)| (a as i32 - b as i32).abs() <= TOLERANCE as i32);
+
+      if matches {
+         let gray = (192 + (actual[0] as u32 + actual[1] as u32 + actual[2] as u32) / 12) as u8;
+         diff.extend_from_slice(&[gray, gray, gray, 255]);
+      } else {
+         mismatches += 1;
+         diff.extend_from_slice(&[255, 0, 0, 255]);
+      }
+   }
+
+   if mismatches > 0 {
+      fs::create_dir_all(&failures).unwrap();
+      renderer.save(&actual_path).unwrap();
+      imagefmt::write(&diff_path, WIDTH as usize, HEIGHT as usize, imagefmt::ColFmt::RGBA, &diff, imagefmt::ColType::Auto).unwrap();
+      panic!("{} pixels differ from {}. See {} and {}", mismatches, reference_path.display(), actual_path.display(), diff_path.display());
+   }
+}
+
+
+
+// A five pointed star drawn in one stroke, so the middle is wound around twice
+fn star(canvas: Canvas, center_x: f32, center_y: f32, radius: f32) -> Canvas {
+   let mut canvas = canvas;
+
+   for i in 0..5 {
+      let angle = i as f32 * 4.0 * consts::PI / 5.0 - consts::PI / 2.0;
+      let (x, y) = (center_x + radius * angle.cos(), center_y + radius * angle.sin());
+      canvas = if i == 0 { canvas.move_to(x, y) } else { canvas.line_to(x, y) };
+   }
+
+   canvas.close_path()
+}
+
+
+fn checkerboard() -> ::std::rc::Rc<Image> {
+   let mut pixels = vec![];
+
+   for y in 0..8 {
+      for x in 0..8 {
+         if (x / 4 + y / 4) % 2 == 0 {
+            pixels.extend_from_slice(&[40, 40, 160, 255]);
+         } else {
+            pixels.extend_from_slice(&[240, 200, 40, 255]);
+         }
+      }
+   }
+
+   Image::from_rgba(8, 8, pixels).unwrap()
+}
+
+
+fn noto_serif() -> ::std::rc::Rc<Font> {
+   Font::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples").join("NotoSerif-Regular.ttf")).unwrap()
+}
+
+
+// Has symbols Noto Serif doesn't, like ✓, for testing fallback
+fn dejavu_sans_mono_path() -> PathBuf {
+   PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fonts").join("DejaVuSansMono.ttf")
+}
+
+
+// A .ttc collection of the given .ttf files. Each font's table directory is copied as is,
+// with its table offsets moved to count from the start of the collection.
+fn collection_of(fonts: &[Vec<u8>]) -> Vec<u8> {
+   let read_u32 = |data: &[u8], at: usize| (data[at] as u32) << 24 | (data[at + 1] as u32) << 16 | (data[at + 2] as u32) << 8 | data[at + 3] as u32;
+   let write_u32 = |data: &mut Vec<u8>, at: usize, value: u32| data[at..at + 4].copy_from_slice(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]);
+
+   let mut collection = b"ttcf\x00\x01\x00\x00".to_vec();
+   collection.extend_from_slice(&[0, 0, 0, fonts.len() as u8]);
+   collection.extend(vec![0; 4 * fonts.len()]);
+
+   for (index, font) in fonts.iter().enumerate() {
+      while collection.len() % 4 != 0 {
+         collection.push(0);
+      }
+
+      let start = collection.len();
+      write_u32(&mut collection, 12 + 4 * index, start as u32);
+      collection.extend_from_slice(font);
+
+      let table_count = (font[4] as usize) << 8 | font[5] as usize;
+      for table in 0..table_count {
+         let record = start + 12 + 16 * table;
+         let offset = read_u32(&collection, record + 8);
+         write_u32(&mut collection, record + 8, offset + start as u32);
+      }
+   }
+
+   collection
+}
+
+
+
+#[test]
+fn concave_polygon() {
+   let canvas = Canvas::new()
+      .move_to(10.0, 10.0)
+      .line_to(118.0, 10.0)
+      .line_to(64.0, 64.0)
+      .line_to(118.0, 118.0)
+      .line_to(10.0, 118.0)
+      .line_to(40.0, 64.0)
+      .fill(0.2, 0.6, 0.3, 1.0);
+
+   check("concave_polygon", &canvas);
+}
+
+
+#[test]
+fn fill_rules() {
+   let canvas = star(Canvas::new(), 32.0, 36.0, 30.0)
+      .fill(0.1, 0.1, 0.1, 1.0);
+   let canvas = star(canvas, 96.0, 36.0, 30.0)
+      .fill_rule(FillRule::EvenOdd)
+      .fill(0.1, 0.1, 0.1, 1.0)
+      .fill_rule(FillRule::NonZero);
+
+   // A square with a hole drawn in the opposite direction, and one drawn in the same direction
+   let canvas = canvas
+      .rectangle(4.0, 72.0, 52.0, 52.0)
+      .move_to(16.0, 84.0).line_to(16.0, 112.0).line_to(44.0, 112.0).line_to(44.0, 84.0).close_path()
+      .fill(0.7, 0.2, 0.2, 1.0)
+      .rectangle(72.0, 72.0, 52.0, 52.0)
+      .rectangle(84.0, 84.0, 28.0, 28.0)
+      .fill(0.2, 0.2, 0.7, 1.0);
+
+   check("fill_rules", &canvas);
+}
+
+
+#[test]
+fn linear_gradients() {
+   let rainbow = Gradient::new()
+      .add_color_stop(0.0, 1.0, 0.0, 0.0, 1.0)
+      .add_color_stop(0.5, 0.0, 1.0, 0.0, 1.0)
+      .add_color_stop(1.0, 0.0, 0.0, 1.0, 1.0);
+
+   let canvas = Canvas::new()
+      .rectangle(0.0, 0.0, 128.0, 40.0)
+      .fill_linear_gradient(32.0, 0.0, 96.0, 0.0, &rainbow)
+      .rectangle(0.0, 44.0, 128.0, 40.0)
+      .fill_linear_gradient(32.0, 0.0, 64.0, 0.0, &rainbow.clone().spread(Spread::Repeat))
+      .rectangle(0.0, 88.0, 128.0, 40.0)
+      .fill_linear_gradient(32.0, 88.0, 64.0, 128.0, &rainbow.clone().spread(Spread::Reflect));
+
+   check("linear_gradients", &canvas);
+}
+
+
+#[test]
+fn radial_and_conic_gradients() {
+   let fade = Gradient::new()
+      .add_color_stop(0.0, 1.0, 0.9, 0.2, 1.0)
+      .add_color_stop(1.0, 0.2, 0.2, 0.8, 0.0);
+   let wheel = Gradient::new()
+      .add_color_stop(0.0, 1.0, 0.0, 0.0, 1.0)
+      .add_color_stop(0.33, 0.0, 1.0, 0.0, 1.0)
+      .add_color_stop(0.67, 0.0, 0.0, 1.0, 1.0)
+      .add_color_stop(1.0, 1.0, 0.0, 0.0, 1.0);
+
+   let canvas = Canvas::new()
+      .rectangle(0.0, 0.0, 64.0, 64.0)
+      .fill_radial_gradient(32.0, 32.0, 0.0, 32.0, 32.0, 30.0, &fade)
+      .rectangle(64.0, 0.0, 64.0, 64.0)
+      .fill_radial_gradient(80.0, 20.0, 4.0, 96.0, 32.0, 28.0, &fade)
+      .circle(64.0, 96.0, 30.0)
+      .fill_conic_gradient(64.0, 96.0, consts::PI / 4.0, &wheel);
+
+   check("radial_and_conic_gradients", &canvas);
+}
+
+
+#[test]
+fn image_fills() {
+   let image = checkerboard();
+
+   let canvas = Canvas::new()
+      .rectangle(4.0, 4.0, 56.0, 56.0)
+      .fill_image(image.clone(), 4.0, 4.0, 28.0, 28.0)
+      .circle(96.0, 32.0, 28.0)
+      .fill_pattern(&Pattern::new(image.clone(), Repetition::Repeat).transform(Matrix::from_rotation(0.3) * Matrix::from_scale(3.0, 3.0)))
+      .rectangle(4.0, 68.0, 120.0, 56.0)
+      .fill_pattern(&Pattern::new(image.clone(), Repetition::RepeatX).transform(Matrix::from_translation(0.0, 80.0) * Matrix::from_scale(2.0, 2.0)));
+
+   check("image_fills", &canvas);
+}
+
+
+#[test]
+fn transforms() {
+   let badge = Canvas::new()
+      .rectangle(-10.0, -10.0, 20.0, 20.0)
+      .fill(0.9, 0.4, 0.1, 1.0);
+
+   let canvas = Canvas::new()
+      .save()
+      .translate(32.0, 32.0)
+      .rotate(consts::PI / 6.0)
+      .rectangle(-20.0, -20.0, 40.0, 40.0)
+      .fill_linear_gradient(-20.0, 0.0, 20.0, 0.0, &Gradient::new()
+         .add_color_stop(0.0, 0.1, 0.3, 0.9, 1.0)
+         .add_color_stop(1.0, 0.9, 0.3, 0.1, 1.0))
+      .restore()
+      .save()
+      .translate(96.0, 32.0)
+      .skew_x(0.4)
+      .scale(1.5, 0.75)
+      .circle(0.0, 0.0, 20.0)
+      .fill(0.2, 0.7, 0.4, 1.0)
+      .restore()
+      .save()
+      .transform(1.0, 0.2, -0.2, 1.0, 64.0, 96.0)
+      .attach(&badge)
+      .translate(30.0, 0.0)
+      .attach(&badge)
+      .translate(-60.0, 0.0)
+      .attach(&badge)
+      .restore();
+
+   check("transforms", &canvas);
+}
+
+
+#[test]
+fn strokes() {
+   let zigzag = |canvas: Canvas, y: f32| {
+      canvas
+         .move_to(12.0, y + 20.0)
+         .line_to(36.0, y)
+         .line_to(60.0, y + 20.0)
+         .line_to(84.0, y)
+         .line_to(116.0, y + 8.0)
+   };
+
+   let canvas = Canvas::new()
+      .line_width(8.0);
+   let canvas = zigzag(canvas, 8.0)
+      .line_join(LineJoin::Miter)
+      .line_cap(LineCap::Butt)
+      .stroke(0.1, 0.1, 0.1, 1.0);
+   let canvas = zigzag(canvas, 40.0)
+      .line_join(LineJoin::Round)
+      .line_cap(LineCap::Round)
+      .stroke(0.1, 0.4, 0.8, 1.0);
+   let canvas = zigzag(canvas, 72.0)
+      .line_join(LineJoin::Bevel)
+      .line_cap(LineCap::Square)
+      .stroke(0.8, 0.3, 0.1, 0.6)
+      .line_width(3.0)
+      .line_cap(LineCap::Butt)
+      .set_line_dash(&[8.0, 4.0])
+      .line_dash_offset(2.0)
+      .rectangle(12.0, 104.0, 104.0, 18.0)
+      .stroke(0.3, 0.3, 0.3, 1.0);
+
+   check("strokes", &canvas);
+}
+
+
+#[test]
+fn zero_length_dash_pattern_is_solid() {
+   let draw = |canvas: Canvas| {
+      let mut renderer = SoftwareRenderer::new(WIDTH, HEIGHT);
+      renderer.draw_canvas(&canvas.rectangle(12.0, 12.0, 104.0, 104.0).line_width(3.0).stroke(0.0, 0.0, 0.0, 1.0));
+      renderer.into_pixels()
+   };
+
+   assert!(draw(Canvas::new()) == draw(Canvas::new().set_line_dash(&[0.0, 0.0])));
+}
+
+
+// Patterns too fine to see would otherwise cut the line into millions of dashes, or never finish
+#[test]
+fn tiny_dash_pattern_is_solid() {
+   let draw = |canvas: Canvas| {
+      let mut renderer = SoftwareRenderer::new(WIDTH, HEIGHT);
+      renderer.draw_canvas(&canvas.move_to(14.0, 64.0).line_to(114.0, 64.0).line_width(3.0).stroke(0.0, 0.0, 0.0, 1.0));
+      renderer.into_pixels()
+   };
+
+   let solid = draw(Canvas::new());
+   assert!(draw(Canvas::new().set_line_dash(&[1e-9, 1e-9])) == solid);
+   assert!(draw(Canvas::new().set_line_dash(&[1e-9, 1e-9]).line_dash_offset(1e9)) == solid);
+   assert!(draw(Canvas::new().set_line_dash(&[0.001, 0.001])) == solid);
+}
+
+
+// Strokes that cross themselves, where a join lands on top of another part of the line
+#[test]
+fn self_overlapping_strokes() {
+   let hook = |canvas: Canvas, x: f32, y: f32| {
+      canvas
+         .move_to(x, y)
+         .line_to(x + 30.0, y)
+         .line_to(x + 30.0, y + 30.0)
+         .line_to(x + 39.0, y + 30.0)
+         .line_to(x + 39.0, y - 9.0)
+   };
+
+   let canvas = Canvas::new()
+      .line_width(12.0);
+   let canvas = hook(canvas, 10.0, 20.0)
+      .line_join(LineJoin::Miter)
+      .stroke(0.1, 0.1, 0.1, 1.0);
+   let canvas = hook(canvas, 72.0, 20.0)
+      .line_join(LineJoin::Round)
+      .stroke(0.1, 0.4, 0.8, 1.0);
+   let canvas = hook(canvas, 10.0, 82.0)
+      .line_join(LineJoin::Bevel)
+      .stroke(0.8, 0.3, 0.1, 1.0)
+      .line_join(LineJoin::Round)
+      .move_to(74.0, 74.0)
+      .line_to(114.0, 114.0)
+      .line_to(114.0, 74.0)
+      .line_to(74.0, 114.0)
+      .close_path()
+      .stroke(0.2, 0.6, 0.2, 1.0);
+
+   check("self_overlapping_strokes", &canvas);
+}
+
+
+#[test]
+fn curves_and_arcs() {
+   let canvas = Canvas::new()
+      .move_to(8.0, 60.0)
+      .quadratic_curve_to(64.0, -40.0, 120.0, 60.0)
+      .bezier_curve_to(90.0, 20.0, 40.0, 100.0, 8.0, 60.0)
+      .fill(0.5, 0.2, 0.7, 1.0)
+      .move_to(64.0, 96.0)
+      .arc(64.0, 96.0, 28.0, -consts::PI / 2.0, consts::PI / 3.0, false)
+      .close_path()
+      .fill(0.9, 0.6, 0.1, 1.0)
+      .ellipse(24.0, 100.0, 16.0, 8.0, consts::PI / 4.0, 0.0, 2.0 * consts::PI, false)
+      .fill(0.1, 0.6, 0.6, 1.0)
+      .rounded_rectangle(92.0, 80.0, 32.0, 40.0, 12.0, 4.0, 0.0, 16.0)
+      .fill(0.2, 0.3, 0.9, 1.0)
+      .line_width(2.0)
+      .move_to(4.0, 4.0)
+      .arc_to(124.0, 4.0, 124.0, 40.0, 16.0)
+      .stroke(0.0, 0.0, 0.0, 1.0);
+
+   check("curves_and_arcs", &canvas);
+}
+
+
+#[test]
+fn text() {
+   let font = noto_serif();
+   let gradient = Gradient::new()
+      .add_color_stop(0.0, 0.9, 0.2, 0.1, 1.0)
+      .add_color_stop(1.0, 0.1, 0.3, 0.9, 1.0);
+
+   let canvas = Canvas::new()
+      .fill_text(font.clone(), "Text", 6.0, 34.0, 32.0)
+      .fill(0.0, 0.0, 0.0, 1.0)
+      .fill_text(font.clone(), "Fill", 6.0, 72.0, 36.0)
+      .fill_linear_gradient(6.0, 0.0, 70.0, 0.0, &gradient)
+      .fill_text(font.clone(), "small print", 6.0, 90.0, 11.0)
+      .fill(0.2, 0.2, 0.2, 1.0)
+      .translate(76.0, 70.0)
+      .rotate(-consts::PI / 4.0)
+      .fill_text(font.clone(), "Tilt", 0.0, 0.0, 24.0)
+      .fill(0.1, 0.5, 0.2, 1.0);
+
+   check("text", &canvas);
+}
+
+
+#[test]
+fn paragraph() {
+   let layout = Paragraph::new(noto_serif(), 11.0, 116.0)
+      .align(TextAlign::Justify)
+      .max_lines(5)
+      .layout("Lines are wrapped to fit the width and justified to fill it, all but the last, which is cut short with an ellipsis when there are too many of them to show");
+
+   assert!(layout.truncated);
+   assert_eq!(layout.lines.len(), 5);
+   assert!(layout.lines[4].text.ends_with("…"));
+
+   let canvas = Canvas::new()
+      .fill_text_layout(&layout, 6.0, 4.0)
+      .fill(0.1, 0.1, 0.1, 1.0);
+
+   check("paragraph", &canvas);
+}
+
+
+#[test]
+fn text_outlines() {
+   let font = noto_serif();
+   let gradient = Gradient::new()
+      .add_color_stop(0.0, 0.9, 0.2, 0.1, 1.0)
+      .add_color_stop(1.0, 0.1, 0.3, 0.9, 1.0);
+
+   let canvas = Canvas::new()
+      .text_path(&font, "Ag", 4.0, 60.0, 64.0)
+      .fill_linear_gradient(4.0, 0.0, 80.0, 0.0, &gradient)
+      .text_path(&font, "Ag", 4.0, 60.0, 64.0)
+      .line_width(1.5)
+      .stroke(0.0, 0.0, 0.0, 1.0)
+      .translate(40.0, 120.0)
+      .rotate(-consts::PI / 6.0)
+      .scale(3.0, 3.0)
+      .text_path(&font, "big", 0.0, 0.0, 12.0)
+      .fill(0.1, 0.5, 0.2, 1.0);
+
+   check("text_outlines", &canvas);
+}
+
+
+#[test]
+fn distance_field_text() {
+   let font = noto_serif();
+
+   let canvas = Canvas::new()
+      .fill_text(font.clone(), "Field", 6.0, 34.0, 32.0)
+      .fill(0.0, 0.0, 0.0, 1.0)
+      .fill_text(font.clone(), "small print", 6.0, 52.0, 11.0)
+      .fill(0.2, 0.2, 0.2, 1.0)
+      .translate(10.0, 120.0)
+      .rotate(-consts::PI / 8.0)
+      .scale(4.0, 4.0)
+      .fill_text(font.clone(), "Zo", 0.0, 0.0, 14.0)
+      .fill(0.1, 0.3, 0.8, 1.0);
+
+   let mut renderer = SoftwareRenderer::new(WIDTH, HEIGHT);
+   renderer.set_text_rendering(TextRendering::DistanceField);
+
+   check_with("distance_field_text", &canvas, renderer);
+}
+
+
+#[test]
+fn font_family() {
+   let serif = noto_serif();
+   let mono = Font::new(dejavu_sans_mono_path()).unwrap();
+   let family = FontFamily::new(serif.clone()).fallback(mono.clone());
+
+   assert!(serif.has_glyph('N'));
+   assert!(!serif.has_glyph('✓'));
+   assert!(mono.has_glyph('✓'));
+   assert_eq!(serif.missing_glyphs("Noto ✓ 世界 世"), vec!['✓', '世', '界']);
+
+   // Each character comes from the first font that has it
+   assert!(::std::rc::Rc::ptr_eq(family.font_for('N').unwrap(), &serif));
+   assert!(::std::rc::Rc::ptr_eq(family.font_for('✓').unwrap(), &mono));
+   assert!(family.font_for('世').is_none());
+   assert_eq!(family.missing_glyphs("Go ✓ 世界"), vec!['世', '界']);
+
+   // So text switches fonts where it needs to
+   let width = family.measure("Go✓", 24.0).width;
+   let expected = serif.measure("Go", 24.0).width + mono.measure("✓", 24.0).width;
+   assert!((width - expected).abs() < 0.01, "{} isn't {}", width, expected);
+
+   // Characters no font has are drawn with the first font's missing glyph symbol
+   let canvas = Canvas::new()
+      .fill_text_family(&family, "Go ✓ 世", 6.0, 40.0, 24.0)
+      .fill(0.0, 0.0, 0.0, 1.0)
+      .text_path_family(&family, "P✓th", 6.0, 90.0, 36.0)
+      .fill(0.1, 0.3, 0.8, 1.0);
+
+   check("font_family", &canvas);
+}
+
+
+#[test]
+fn font_collections() {
+   let serif_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples").join("NotoSerif-Regular.ttf");
+   let serif_data = fs::read(&serif_path).unwrap();
+   let mono_data = fs::read(dejavu_sans_mono_path()).unwrap();
+
+   // A plain font file is a collection of one
+   assert!(Font::new_at(&serif_path, 0).is_ok());
+   assert!(Font::new_at(&serif_path, 1).is_err());
+   assert_eq!(Font::collection(&serif_path).unwrap().len(), 1);
+
+   // Faces in a .ttc are picked by index
+   let collection = collection_of(&[serif_data, mono_data]);
+   let faces = Font::collection_from_bytes(collection.clone()).unwrap();
+   assert_eq!(faces.len(), 2);
+   assert!(!faces[0].has_glyph('✓'));
+   assert!(faces[1].has_glyph('✓'));
+
+   assert!(!Font::from_bytes(collection.clone()).unwrap().has_glyph('✓'));
+   assert!(Font::from_bytes_at(collection.clone(), 1).unwrap().has_glyph('✓'));
+   assert!(Font::from_bytes_at(collection.clone(), 2).is_err());
+   assert_eq!(Font::from_bytes_at(collection, ::std::usize::MAX).unwrap_err().kind(), ::std::io::ErrorKind::InvalidInput);
+
+   assert!(Font::from_bytes_at(vec![0; 64], 0).is_err());
+}
+
+
+#[test]
+fn jpeg_output_is_unsupported() {
+   let path = env::temp_dir().join("picasso-unsupported.jpg");
+
+   match Canvas::new().rectangle(0.0, 0.0, 8.0, 8.0).fill(0.0, 0.0, 0.0, 1.0).render_to_file(&path, 8, 8) {
+      Err(imagefmt::Error::Unsupported(_)) => {},
+      other => panic!("expected an Unsupported error, got {:?}", other),
+   }
+
+   assert!(!path.exists());
+}
+
+
+#[test]
+fn stroke_keeps_text_for_the_next_fill() {
+   let font = noto_serif();
+   let draw = |canvas: Canvas| {
+      let mut renderer = SoftwareRenderer::new(WIDTH, HEIGHT);
+      renderer.draw_canvas(&canvas);
+      renderer.into_pixels()
+   };
+
+   let stroked_in_between = Canvas::new()
+      .fill_text(font.clone(), "Kept", 10.0, 60.0, 24.0)
+      .rectangle(4.0, 4.0, 120.0, 120.0)
+      .stroke(0.5, 0.5, 0.5, 1.0)
+      .fill(0.0, 0.0, 0.0, 1.0);
+
+   let stroked_first = Canvas::new()
+      .rectangle(4.0, 4.0, 120.0, 120.0)
+      .stroke(0.5, 0.5, 0.5, 1.0)
+      .fill_text(font.clone(), "Kept", 10.0, 60.0, 24.0)
+      .fill(0.0, 0.0, 0.0, 1.0);
+
+   assert!(draw(stroked_in_between) == draw(stroked_first));
+}
+
+
+#[test]
+fn paragraph_without_room() {
+   // Every character gets a line of its own rather than wrapping forever
+   for &width in &[0.0, -10.0, ::std::f32::NAN] {
+      let layout = Paragraph::new(noto_serif(), 11.0, width).layout("ab cd");
+      let lines: Vec<&str> = layout.lines.iter().map(|line| line.text.as_str()).collect();
+      assert_eq!(lines, vec!["a", "b", "c", "d"]);
+   }
+}